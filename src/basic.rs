@@ -1,14 +1,20 @@
 //! The most basic usage for [`build_script`](crate).
 //! # Notes
 //! 99% of the time, all of the public functions in this crate can suffice.
-use crate::BuildScript;
+//!
+//! All of the functions share a global [`BuildScript`](BuildScript), which writes to stdout
+//! immediately by default. It can be replaced with [`install()`](install) or reconfigured with
+//! [`configure()`](configure), such as to buffer, deduplicate or redirect what libraries write
+//! through these functions.
 use crate::{
     cargo_rustc_link_lib as cargo_rustc_link_lib_,
     cargo_rustc_link_search as cargo_rustc_link_search_,
 };
+use crate::{BuildScript, Instruction};
 use once_cell::sync::Lazy;
 use std::path::PathBuf;
-use std::sync::{LockResult, Mutex, MutexGuard};
+use std::sync::{LockResult, Mutex, MutexGuard, PoisonError};
+use std::{io, mem};
 
 static BUILD_SCRIPT: Lazy<Mutex<BuildScript>> = Lazy::new(|| {
     let mut build_script = BuildScript::default();
@@ -17,43 +23,126 @@ static BUILD_SCRIPT: Lazy<Mutex<BuildScript>> = Lazy::new(|| {
     Mutex::new(build_script)
 });
 
-/// Lock the mutex of build script mutex. This panics if the mutex is poisoned.
+/// Held while capturing, so only one capture happens at a time.
+static CAPTURE: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Lock the mutex of build script mutex. Poisoning is ignored, since a panicking capture poisons
+/// it while restoring the global build script, which is always left whole.
 fn lock_mutex<T>(lock: LockResult<MutexGuard<T>>) -> MutexGuard<T> {
-    lock.expect("mutex is poisoned")
+    lock.unwrap_or_else(PoisonError::into_inner)
 }
 
 /// Wrapper for locking the build script mutex. Internally this handles locking the build script
-/// mutex and then panicking if mutex is poisoned.
+/// mutex and then recovering it if the mutex is poisoned.
 fn build_script() -> MutexGuard<'static, BuildScript<'static>> {
     lock_mutex(BUILD_SCRIPT.lock())
 }
 
+/// Replace the global build script used by the functions in this module. This only succeeds
+/// before anything has been written or queued, otherwise this returns `false` and `build_script`
+/// is dropped.
+/// # Notes
+/// If the new build script isn't in `now` mode, nothing is written until [`flush()`](flush) is
+/// called.
+pub fn install(build_script: BuildScript<'static>) -> bool {
+    let mut global = self::build_script();
+
+    if global.has_written() || !global.is_empty() {
+        return false;
+    }
+
+    *global = build_script;
+
+    true
+}
+
+/// Reconfigure the global build script used by the functions in this module, such as to set its
+/// [`dedup()`](BuildScript::dedup) policy.
+pub fn configure<R>(func: impl FnOnce(&mut BuildScript<'static>) -> R) -> R {
+    func(&mut build_script())
+}
+
+/// Write all the queued instructions of the global build script. See
+/// [`BuildScript::build()`](BuildScript::build).
+pub fn flush() {
+    build_script().build()
+}
+
+/// Returns `true` if the global build script has written anything yet.
+pub fn has_written() -> bool {
+    build_script().has_written()
+}
+
+/// Lock the capture mutex. A panicking capture already restored the global build script, so
+/// poisoning is ignored.
+fn capture_lock() -> MutexGuard<'static, ()> {
+    CAPTURE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Restores the global build script when dropped, even when panicking.
+struct Restore(Option<BuildScript<'static>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            *build_script() = previous
+        }
+    }
+}
+
+/// Call `func`, capturing every instruction passed through the functions in this module instead of
+/// writing them. The global build script is restored afterwards, even if `func` panics.
+/// # Notes
+/// Captures happen one at a time, so concurrent tests capturing won't see each other's
+/// instructions. However, calls from other threads which aren't capturing are captured as well.
+/// Captures can't be nested, since the inner one would wait on the outer one forever.
+/// # Examples
+/// ```rust
+/// let instructions = build_script::capture(|| build_script::cargo_rustc_link_lib("ssl"));
+/// assert_eq!(instructions[0].to_string(), "cargo:rustc-link-lib=ssl");
+/// ```
+pub fn capture(func: impl FnOnce()) -> Vec<Instruction> {
+    let _lock = capture_lock();
+    let buffer = BuildScript::new(Box::leak(Box::new(io::sink())));
+    let mut restore = Restore(Some(mem::replace(&mut *build_script(), buffer)));
+    func();
+    let previous = restore.0.take().expect("build script was already restored");
+
+    mem::replace(&mut *build_script(), previous).take_pending()
+}
+
 /// Wrapper for `cargo:rerun-if-changed=PATH`. This tells Cargo when to rerun the script.
+#[track_caller]
 pub fn cargo_rerun_if_changed(path: impl Into<PathBuf>) {
     build_script().cargo_rerun_if_changed(path.into());
 }
 
 /// Wrapper for `cargo:rerun-if-env-changed=VAR`. This tells Cargo when to rerun the script.
+#[track_caller]
 pub fn cargo_rerun_if_env_changed(var: impl Into<String>) {
     build_script().cargo_rerun_if_env_changed(&var.into());
 }
 
 /// Wrapper for `cargo:rustc-link-lib=[KIND=]NAME`. This adds a library to link.
+#[track_caller]
 pub fn cargo_rustc_link_lib(name: impl Into<String>) {
     build_script().cargo_rustc_link_lib(None, &name.into());
 }
 
 /// [`cargo_rustc_link_lib()`](cargo_rustc_link_lib), but with the `kind` parameter needed.
+#[track_caller]
 pub fn cargo_rustc_link_lib_mapping(kind: cargo_rustc_link_lib_::Kind, name: impl Into<String>) {
     build_script().cargo_rustc_link_lib(kind.into(), &name.into());
 }
 
 /// Wrapper for `cargo:rustc-link-search=[KIND=]PATH`. This adds to the library search path.
+#[track_caller]
 pub fn cargo_rustc_link_search(path: impl Into<PathBuf>) {
     build_script().cargo_rustc_link_search(None, path.into());
 }
 
 /// [`cargo_rustc_link_search()`](cargo_rustc_link_search), but with the `kind` parameter needed.
+#[track_caller]
 pub fn cargo_rustc_link_search_mapping(
     kind: cargo_rustc_link_search_::Kind,
     path: impl Into<PathBuf>,
@@ -62,66 +151,151 @@ pub fn cargo_rustc_link_search_mapping(
 }
 
 /// Wrapper for `cargo:rustc-flags=FLAGS`. This passes certain flags to the compiler.
+#[track_caller]
 pub fn cargo_rustc_flags(flags: impl Into<String>) {
     build_script().cargo_rustc_flags(&flags.into());
 }
 
 /// Wrapper for `cargo:rustc-cfg=KEY[="VALUE"]`. This enable compile-time `cfg` settings.
+#[track_caller]
 pub fn cargo_rustc_cfg(key: impl Into<String>) {
     build_script().cargo_rustc_cfg(&key.into(), None);
 }
 
 /// [`cargo_rustc_cfg()`](cargo_rustc_cfg), but with the `value` parameter needed.
+#[track_caller]
 pub fn cargo_rustc_cfg_mapping(key: impl Into<String>, value: impl Into<String>) {
     build_script().cargo_rustc_cfg(&key.into(), Some(&value.into()));
 }
 
+/// Wrapper for `cargo:rustc-check-cfg=CHECK_CFG`. This declares the expected `cfg`s, so the
+/// `unexpected_cfgs` lint doesn't warn about them.
+#[track_caller]
+pub fn cargo_rustc_check_cfg(check_cfg: impl Into<String>) {
+    build_script().cargo_rustc_check_cfg(&check_cfg.into());
+}
+
 /// Wrapper for `cargo:rustc-env=VAR=VALUE`. This sets an environment variable.
+#[track_caller]
 pub fn cargo_rustc_env(var: impl Into<String>, value: impl Into<String>) {
     build_script().cargo_rustc_env(&var.into(), &value.into());
 }
 
 /// Wrapper for `cargo:rustc-cdylib-link-arg=FLAG`. This passes custom flags to a linker for cdylib
 /// crates.
+#[track_caller]
 pub fn cargo_rustc_cdylib_link_arg(flag: impl Into<String>) {
     build_script().cargo_rustc_cdylib_link_arg(&flag.into());
 }
 
 /// Wrapper for `cargo:warning=MESSAGE`. This displays a warning on the terminal.
+#[track_caller]
 pub fn cargo_warning(message: impl Into<String>) {
     build_script().cargo_warning(&message.into());
 }
 
+/// Wrapper for `cargo::error=MESSAGE`. This displays an error on the terminal and fails the
+/// build. See [`BuildScript::cargo_error()`](BuildScript::cargo_error).
+#[track_caller]
+pub fn cargo_error(message: impl Into<String>) {
+    build_script().cargo_error(&message.into());
+}
+
 /// Wrapper for `cargo:KEY=VALUE`. This is metadata, used by `links` scripts.
+#[track_caller]
 pub fn cargo_mapping(key: impl Into<String>, value: impl Into<String>) {
     build_script().cargo_mapping(&key.into(), &value.into());
 }
 
 #[cfg(test)]
 mod tests {
-    use gag::BufferRedirect;
-    use serial_test::serial;
-    use std::io::Read;
+    fn test(func: impl FnOnce(), expected: &str) -> bool {
+        let captured = super::capture(func);
 
-    fn test(func: impl Fn(), expected: &str) -> bool {
-        let mut output = String::new();
-        let mut buffer = BufferRedirect::stdout().unwrap();
-        func();
-        buffer.read_to_string(&mut output).unwrap();
-
-        output.contains(expected)
+        captured.len() == 1 && captured[0].to_string() == expected
     }
 
     macro_rules! new_test {
         ($name:ident, $func:expr, $expected:literal) => {
             #[test]
-            #[serial]
             fn $name() {
                 assert!(test($func, $expected))
             }
         };
     }
 
+    #[test]
+    fn test_capture() {
+        let captured = super::capture(|| {
+            super::cargo_rustc_link_lib("ssl");
+            super::cargo_rustc_cfg("ossl");
+        });
+        let captured: Vec<_> = captured.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            captured,
+            vec!["cargo:rustc-link-lib=ssl", "cargo:rustc-cfg=ossl"]
+        )
+    }
+
+    #[test]
+    fn test_capture_panic() {
+        let result = std::panic::catch_unwind(|| {
+            super::capture(|| {
+                super::cargo_rustc_link_lib("ssl");
+                panic!("failed")
+            })
+        });
+        assert!(result.is_err());
+        assert!(super::build_script().is_empty());
+        assert!(test(
+            || super::cargo_rustc_link_lib("crypto"),
+            "cargo:rustc-link-lib=crypto"
+        ))
+    }
+
+    #[test]
+    fn test_install() {
+        use crate::BuildScript;
+        use std::sync::{Arc, Mutex};
+
+        let _lock = super::capture_lock();
+
+        #[derive(Clone, Default)]
+        struct Writer(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Writer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let writer = Writer::default();
+        let previous = std::mem::replace(
+            &mut *super::build_script(),
+            BuildScript::new(Box::leak(Box::new(std::io::sink()))),
+        );
+        let build_script = BuildScript::new(Box::leak(Box::new(writer.clone())));
+        assert!(super::install(build_script));
+        super::configure(|build_script| {
+            build_script.dedup(crate::policy::Dedup::KeepFirst);
+        });
+        super::cargo_rerun_if_changed("path");
+        super::cargo_rerun_if_changed("path");
+        assert!(!super::has_written());
+        let build_script = BuildScript::new(Box::leak(Box::new(std::io::sink())));
+        assert!(!super::install(build_script));
+        super::flush();
+        assert!(super::has_written());
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        *super::build_script() = previous;
+
+        assert_eq!(output, "cargo:rerun-if-changed=path\n")
+    }
+
     new_test!(
         test_cargo_rerun_if_changed,
         || super::cargo_rerun_if_changed("path"),
@@ -139,7 +313,6 @@ mod tests {
     );
 
     #[test]
-    #[serial]
     fn test_cargo_rustc_link_lib_mapping() {
         use crate::cargo_rustc_link_lib::Kind;
 
@@ -163,7 +336,6 @@ mod tests {
     );
 
     #[test]
-    #[serial]
     fn test_cargo_rustc_link_search_mapping() {
         use crate::cargo_rustc_link_search::Kind;
 
@@ -196,6 +368,11 @@ mod tests {
         || super::cargo_rustc_cfg("key"),
         "cargo:rustc-cfg=key"
     );
+    new_test!(
+        test_cargo_rustc_check_cfg,
+        || super::cargo_rustc_check_cfg("cfg(key)"),
+        "cargo:rustc-check-cfg=cfg(key)"
+    );
     new_test!(
         test_cargo_rustc_cfg_mapping,
         || super::cargo_rustc_cfg_mapping("key", "value"),
@@ -1,4 +1,7 @@
 //! A wrapper for [`cargo_rustc_link_lib`](crate::BuildScript::cargo_rustc_link_lib).
+use std::fmt;
+use std::str::FromStr;
+
 /// A kind for [`cargo_rustc_link_lib`](crate::BuildScript::cargo_rustc_link_lib).
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum Kind {
@@ -40,6 +43,48 @@ impl From<Kind> for String {
     }
 }
 
+impl FromStr for Kind {
+    type Err = ParseKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            Self::DYNAMIC_LIBRARY => Ok(Self::DynamicLibrary),
+            Self::STATIC => Ok(Self::Static),
+            Self::FRAMEWORK => Ok(Self::Framework),
+            _ => Err(ParseKindError(s.into())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Kind {
+    /// Serialized as the name known to the compiler, such as `"static"`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str((*self).into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Kind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let kind = String::deserialize(deserializer)?;
+
+        kind.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// An error returned when a string isn't a known [`Kind`](Kind).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseKindError(String);
+
+impl fmt::Display for ParseKindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown library kind `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseKindError {}
+
 #[cfg(test)]
 mod tests {
     use super::Kind;
@@ -53,4 +98,22 @@ mod tests {
         let kind: String = Kind::Framework.into();
         assert_eq!(kind, Kind::FRAMEWORK)
     }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("dylib".parse(), Ok(Kind::DynamicLibrary));
+        assert_eq!("static".parse(), Ok(Kind::Static));
+        assert_eq!("framework".parse(), Ok(Kind::Framework));
+        assert!("native".parse::<Kind>().is_err())
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let json = serde_json::to_string(&Kind::DynamicLibrary).unwrap();
+        assert_eq!(json, "\"dylib\"");
+        let kind: Kind = serde_json::from_str("\"static\"").unwrap();
+        assert_eq!(kind, Kind::Static);
+        assert!(serde_json::from_str::<Kind>("\"native\"").is_err())
+    }
 }
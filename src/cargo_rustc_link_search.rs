@@ -1,4 +1,7 @@
 //! A wrapper for [`cargo_rustc_link_search`](crate::BuildScript::cargo_rustc_link_search).
+use std::fmt;
+use std::str::FromStr;
+
 /// A kind for [`cargo_rustc_link_search`](crate::BuildScript::cargo_rustc_link_search).
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum Kind {
@@ -54,6 +57,50 @@ impl From<Kind> for String {
     }
 }
 
+impl FromStr for Kind {
+    type Err = ParseKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            Self::DEPENDENCY => Ok(Self::Dependency),
+            Self::CRATE => Ok(Self::Crate),
+            Self::NATIVE => Ok(Self::Native),
+            Self::FRAMEWORK => Ok(Self::Framework),
+            Self::ALL => Ok(Self::All),
+            _ => Err(ParseKindError(s.into())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Kind {
+    /// Serialized as the name known to the compiler, such as `"native"`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str((*self).into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Kind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let kind = String::deserialize(deserializer)?;
+
+        kind.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// An error returned when a string isn't a known [`Kind`](Kind).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseKindError(String);
+
+impl fmt::Display for ParseKindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown search path kind `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseKindError {}
+
 #[cfg(test)]
 mod tests {
     use super::Kind;
@@ -71,4 +118,24 @@ mod tests {
         let kind: String = Kind::All.into();
         assert_eq!(kind, Kind::ALL);
     }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("dependency".parse(), Ok(Kind::Dependency));
+        assert_eq!("crate".parse(), Ok(Kind::Crate));
+        assert_eq!("native".parse(), Ok(Kind::Native));
+        assert_eq!("framework".parse(), Ok(Kind::Framework));
+        assert_eq!("all".parse(), Ok(Kind::All));
+        assert!("static".parse::<Kind>().is_err())
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let json = serde_json::to_string(&Kind::Native).unwrap();
+        assert_eq!(json, "\"native\"");
+        let kind: Kind = serde_json::from_str("\"dependency\"").unwrap();
+        assert_eq!(kind, Kind::Dependency);
+        assert!(serde_json::from_str::<Kind>("\"dylib\"").is_err())
+    }
 }
@@ -0,0 +1,349 @@
+//! Parsing and evaluating `cfg` expressions, such as `all(target_os = "linux", target_env = "gnu")`.
+//! # Notes
+//! Expressions are evaluated against the target being compiled for, through the `CARGO_CFG_*`
+//! environment variables cargo sets for build scripts, not against the host like `cfg!`. A
+//! `feature = "name"` predicate is evaluated through `CARGO_FEATURE_<NAME>` instead, since cargo
+//! doesn't pass features as `CARGO_CFG_*`.
+use std::{env, fmt, str};
+
+/// A `cfg` expression.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Expr {
+    /// A name, such as `unix`.
+    Name(String),
+
+    /// A key and value, such as `target_os = "linux"`.
+    KeyValue(String, String),
+
+    /// `all(...)`. This holds if every expression does, including when there are none.
+    All(Vec<Expr>),
+
+    /// `any(...)`. This holds if at least one expression does, so not when there are none.
+    Any(Vec<Expr>),
+
+    /// `not(...)`.
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluate the expression. `var` gets the value of an environment variable, if it is set.
+    pub fn eval(&self, var: &dyn Fn(&str) -> Option<String>) -> bool {
+        match self {
+            Self::Name(name) => var(&cfg_var(name)).is_some(),
+            Self::KeyValue(key, value) if key == "feature" => var(&feature_var(value)).is_some(),
+            Self::KeyValue(key, value) => var(&cfg_var(key))
+                .map(|values| values.split(',').any(|found| found == value))
+                .unwrap_or(false),
+            Self::All(exprs) => exprs.iter().all(|expr| expr.eval(var)),
+            Self::Any(exprs) => exprs.iter().any(|expr| expr.eval(var)),
+            Self::Not(expr) => !expr.eval(var),
+        }
+    }
+
+    /// Evaluate the expression against the target being compiled for.
+    pub fn holds(&self) -> bool {
+        self.eval(&|var| env::var(var).ok())
+    }
+}
+
+impl str::FromStr for Expr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.expr()?;
+
+        match parser.tokens.next() {
+            Some(token) => Err(Error::Unexpected(token.to_string())),
+            None => Ok(expr),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, name: &str, exprs: &[Expr]| {
+            write!(f, "{}(", name)?;
+
+            for (index, expr) in exprs.iter().enumerate() {
+                if index != 0 {
+                    write!(f, ", ")?
+                }
+
+                write!(f, "{}", expr)?
+            }
+
+            write!(f, ")")
+        };
+
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::KeyValue(key, value) => write!(f, "{} = {:?}", key, value),
+            Self::All(exprs) => list(f, "all", exprs),
+            Self::Any(exprs) => list(f, "any", exprs),
+            Self::Not(expr) => write!(f, "not({})", expr),
+        }
+    }
+}
+
+/// The environment variable cargo sets for a `cfg` of the target.
+fn cfg_var(name: &str) -> String {
+    format!("CARGO_CFG_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// The environment variable cargo sets for an enabled feature.
+fn feature_var(name: &str) -> String {
+    format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// A token of a `cfg` expression.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Equals,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "{}", ident),
+            Self::Str(string) => write!(f, "{:?}", string),
+            Self::Open => write!(f, "("),
+            Self::Close => write!(f, ")"),
+            Self::Comma => write!(f, ","),
+            Self::Equals => write!(f, "="),
+        }
+    }
+}
+
+/// Split a `cfg` expression into tokens.
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '=' => Token::Equals,
+            '"' => {
+                let string: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|c| *c != '"')
+                    .collect();
+
+                if !s[start + 1..].contains('"') {
+                    return Err(Error::UnterminatedString);
+                }
+
+                Token::Str(string)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+
+                while let Some((index, c)) = chars.peek().copied() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+
+                Token::Ident(s[start..end].into())
+            }
+            c if c.is_whitespace() => continue,
+            c => return Err(Error::Unexpected(c.into())),
+        };
+
+        tokens.push(token)
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of a `cfg` expression.
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: std::iter::Peekable<I>,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    /// Take the next token, which must be `expected`.
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        match self.tokens.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(Error::Unexpected(token.to_string())),
+            None => Err(Error::UnexpectedEnd),
+        }
+    }
+
+    /// Parse an expression.
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let ident = match self.tokens.next() {
+            Some(Token::Ident(ident)) => ident,
+            Some(token) => return Err(Error::Unexpected(token.to_string())),
+            None => return Err(Error::UnexpectedEnd),
+        };
+
+        match self.tokens.peek() {
+            Some(Token::Open) => {
+                self.tokens.next();
+
+                match ident.as_str() {
+                    "all" => Ok(Expr::All(self.list()?)),
+                    "any" => Ok(Expr::Any(self.list()?)),
+                    "not" => {
+                        let expr = self.expr()?;
+                        self.expect(Token::Close)?;
+
+                        Ok(Expr::Not(Box::new(expr)))
+                    }
+                    _ => Err(Error::UnknownPredicate(ident)),
+                }
+            }
+            Some(Token::Equals) => {
+                self.tokens.next();
+
+                match self.tokens.next() {
+                    Some(Token::Str(value)) => Ok(Expr::KeyValue(ident, value)),
+                    Some(token) => Err(Error::Unexpected(token.to_string())),
+                    None => Err(Error::UnexpectedEnd),
+                }
+            }
+            _ => Ok(Expr::Name(ident)),
+        }
+    }
+
+    /// Parse a comma separated list of expressions after `(`, up to and including `)`. A trailing
+    /// comma is allowed.
+    fn list(&mut self) -> Result<Vec<Expr>, Error> {
+        let mut exprs = Vec::new();
+
+        loop {
+            if let Some(Token::Close) = self.tokens.peek() {
+                self.tokens.next();
+
+                return Ok(exprs);
+            }
+
+            exprs.push(self.expr()?);
+
+            match self.tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::Close) => return Ok(exprs),
+                Some(token) => return Err(Error::Unexpected(token.to_string())),
+                None => return Err(Error::UnexpectedEnd),
+            }
+        }
+    }
+}
+
+/// An error which occurred while parsing a `cfg` expression.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// The expression ended early.
+    UnexpectedEnd,
+
+    /// Something unexpected was found.
+    Unexpected(String),
+
+    /// A string wasn't closed.
+    UnterminatedString,
+
+    /// A predicate other than `all`, `any` or `not` was used.
+    UnknownPredicate(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of cfg expression"),
+            Self::Unexpected(found) => write!(f, "unexpected `{}` in cfg expression", found),
+            Self::UnterminatedString => write!(f, "unterminated string in cfg expression"),
+            Self::UnknownPredicate(name) => write!(f, "unknown cfg predicate `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Expr};
+
+    fn var(var: &str) -> Option<String> {
+        let value = match var {
+            "CARGO_CFG_UNIX" => "",
+            "CARGO_CFG_TARGET_OS" => "linux",
+            "CARGO_CFG_TARGET_ENV" => "gnu",
+            "CARGO_CFG_TARGET_FEATURE" => "fxsr,sse,sse2",
+            "CARGO_FEATURE_VENDORED_SSL" => "1",
+            _ => return None,
+        };
+
+        Some(value.into())
+    }
+
+    fn eval(expr: &str) -> bool {
+        expr.parse::<Expr>().unwrap().eval(&var)
+    }
+
+    #[test]
+    fn test_parse() {
+        let expr: Expr = r#"all(target_os = "linux", not(any(windows, target_env="musl",)))"#
+            .parse()
+            .unwrap();
+        let expected = Expr::All(vec![
+            Expr::KeyValue("target_os".into(), "linux".into()),
+            Expr::Not(Box::new(Expr::Any(vec![
+                Expr::Name("windows".into()),
+                Expr::KeyValue("target_env".into(), "musl".into()),
+            ]))),
+        ]);
+        assert_eq!(expr, expected);
+        assert_eq!(
+            expr.to_string(),
+            r#"all(target_os = "linux", not(any(windows, target_env = "musl")))"#
+        );
+        assert_eq!(expr.to_string().parse::<Expr>().unwrap(), expected)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |expr: &str| expr.parse::<Expr>().unwrap_err();
+        assert_eq!(error(""), Error::UnexpectedEnd);
+        assert_eq!(error("all(unix"), Error::UnexpectedEnd);
+        assert_eq!(error("unix windows"), Error::Unexpected("windows".into()));
+        assert_eq!(
+            error("target_os = linux"),
+            Error::Unexpected("linux".into())
+        );
+        assert_eq!(error(r#"target_os = "linux"#), Error::UnterminatedString);
+        assert_eq!(
+            error("either(unix)"),
+            Error::UnknownPredicate("either".into())
+        );
+        assert_eq!(error("not(unix, windows)"), Error::Unexpected(",".into()))
+    }
+
+    #[test]
+    fn test_eval() {
+        assert!(eval("unix"));
+        assert!(!eval("windows"));
+        assert!(eval(r#"all(target_os = "linux", target_env = "gnu")"#));
+        assert!(!eval(r#"target_env = "musl""#));
+        assert!(eval(r#"target_feature = "sse2""#));
+        assert!(eval(r#"not(target_feature = "avx")"#));
+        assert!(eval(r#"feature = "vendored-ssl""#));
+        assert!(eval("all()"));
+        assert!(!eval("any()"))
+    }
+}
@@ -0,0 +1,230 @@
+//! Detecting instructions in the stack which disagree with each other.
+//! # Notes
+//! The following are considered conflicts:
+//! - the same library linked with different kinds through `rustc-link-lib`, such as `static=z`
+//!   and `dylib=z`. A library without a kind is considered a `dylib`, like `rustc` does, and
+//!   modifiers are ignored, so `static:+whole-archive=z` doesn't conflict with `static=z`.
+//! - the same environment variable set to different values through `rustc-env`.
+//! - the same `cfg` set both with and without a value through `rustc-cfg`.
+use crate::{Instruction, Prefix, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::Location;
+
+/// What to do when conflicting instructions are found on [`build()`](crate::BuildScript::build).
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Resolution {
+    /// Don't look for conflicts. This is the default.
+    Ignore,
+
+    /// Fail the build. See [`try_build()`](crate::BuildScript::try_build).
+    Error,
+
+    /// Keep every instruction, but write a `cargo:warning` for every conflict.
+    Warn,
+
+    /// Keep the first instruction, removing the later ones which conflict with it.
+    FirstWins,
+
+    /// Keep the last instruction, removing the earlier ones which conflict with it.
+    LastWins,
+}
+
+impl Default for Resolution {
+    /// The default is [`Ignore`](Self::Ignore).
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// An instruction and where it was passed from.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Origin {
+    /// The instruction.
+    pub instruction: Instruction,
+
+    /// Where the instruction was passed from.
+    pub location: &'static Location<'static>,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` (at {})", self.instruction, self.location)
+    }
+}
+
+/// Two instructions which disagree with each other.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conflict {
+    /// The instruction passed first.
+    pub first: Origin,
+
+    /// The instruction passed later.
+    pub second: Origin,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "conflicting instructions: {} and {}",
+            self.first, self.second
+        )
+    }
+}
+
+/// What conflicting instructions have in common, and what they disagree on.
+fn key(instruction: &Instruction) -> Option<((&str, String), String)> {
+    let name = match (&instruction.prefix, &instruction.name) {
+        (Prefix::Cargo, Some(name)) => name.as_str(),
+        _ => return None,
+    };
+
+    match (name, &instruction.value) {
+        ("rustc-link-lib", Value::UnquotedOptionalKey(kind, library)) => {
+            let kind = kind.as_deref().unwrap_or("dylib");
+            let kind = kind.split(':').next().unwrap_or(kind);
+
+            Some(((name, library.clone()), kind.into()))
+        }
+        ("rustc-env", Value::UnquotedMapping(var, value)) => {
+            Some(((name, var.clone()), value.clone()))
+        }
+        ("rustc-cfg", Value::OptionalValue(key, value)) => {
+            Some(((name, key.clone()), value.is_some().to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Find the conflicts in a list of instructions and where they were passed from. Returns the
+/// conflicts, and the indices of the instructions which lost according to `resolution`.
+pub(crate) fn detect(
+    origins: &[(&Instruction, &'static Location<'static>)],
+    resolution: Resolution,
+) -> (Vec<Conflict>, Vec<usize>) {
+    let mut conflicts = Vec::new();
+    let mut losers = Vec::new();
+
+    if let Resolution::Ignore = resolution {
+        return (conflicts, losers);
+    }
+
+    let mut groups: BTreeMap<_, Vec<(usize, String)>> = BTreeMap::new();
+
+    for (index, (instruction, _)) in origins.iter().enumerate() {
+        if let Some((key, variant)) = key(instruction) {
+            groups.entry(key).or_default().push((index, variant))
+        }
+    }
+
+    let origin = |index: usize| Origin {
+        instruction: origins[index].0.clone(),
+        location: origins[index].1,
+    };
+
+    for group in groups.values() {
+        let (first, first_variant) = &group[0];
+        let mut reported = vec![first_variant];
+
+        for (index, variant) in group {
+            if !reported.contains(&variant) {
+                reported.push(variant);
+                conflicts.push(Conflict {
+                    first: origin(*first),
+                    second: origin(*index),
+                })
+            }
+        }
+
+        let winner = match resolution {
+            Resolution::FirstWins => first_variant,
+            Resolution::LastWins => &group[group.len() - 1].1,
+            _ => continue,
+        };
+
+        losers.extend(
+            group
+                .iter()
+                .filter(|(_, variant)| variant != winner)
+                .map(|(index, _)| *index),
+        )
+    }
+
+    losers.sort_unstable();
+
+    (conflicts, losers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolution;
+    use crate::{Instruction, Value};
+    use std::panic::Location;
+
+    fn link_lib(kind: Option<&str>, name: &str) -> Instruction {
+        Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(kind.map(Into::into), name.into()),
+        )
+    }
+
+    fn detect(instructions: &[Instruction], resolution: Resolution) -> (usize, Vec<usize>) {
+        let location = Location::caller();
+        let origins: Vec<_> = instructions
+            .iter()
+            .map(|instruction| (instruction, location))
+            .collect();
+        let (conflicts, losers) = super::detect(&origins, resolution);
+
+        (conflicts.len(), losers)
+    }
+
+    #[test]
+    fn test_link_lib() {
+        let instructions = vec![
+            link_lib(Some("static"), "z"),
+            link_lib(None, "ssl"),
+            link_lib(Some("dylib"), "z"),
+            link_lib(Some("dylib"), "ssl"),
+            link_lib(Some("static"), "z"),
+        ];
+        assert_eq!(detect(&instructions, Resolution::Ignore), (0, vec![]));
+        assert_eq!(detect(&instructions, Resolution::Error), (1, vec![]));
+        assert_eq!(detect(&instructions, Resolution::FirstWins), (1, vec![2]));
+        assert_eq!(detect(&instructions, Resolution::LastWins), (1, vec![2]));
+        let instructions = vec![
+            link_lib(Some("static:+whole-archive"), "z"),
+            link_lib(Some("static"), "z"),
+            link_lib(Some("dylib:+verbatim"), "z"),
+        ];
+        assert_eq!(detect(&instructions, Resolution::Error), (1, vec![]));
+    }
+
+    #[test]
+    fn test_env() {
+        let env = |value: &str| {
+            Instruction::new(
+                "rustc-env",
+                Value::UnquotedMapping("VERSION".into(), value.into()),
+            )
+        };
+        let instructions = vec![env("1"), env("2"), env("3")];
+        assert_eq!(detect(&instructions, Resolution::Warn), (2, vec![]));
+        assert_eq!(detect(&instructions, Resolution::LastWins), (2, vec![0, 1]))
+    }
+
+    #[test]
+    fn test_cfg() {
+        let cfg = |value: Option<&str>| {
+            Instruction::new(
+                "rustc-cfg",
+                Value::OptionalValue("key".into(), value.map(Into::into)),
+            )
+        };
+        let instructions = vec![cfg(Some("a")), cfg(Some("b"))];
+        assert_eq!(detect(&instructions, Resolution::Error), (0, vec![]));
+        let instructions = vec![cfg(Some("a")), cfg(None)];
+        assert_eq!(detect(&instructions, Resolution::FirstWins), (1, vec![1]))
+    }
+}
@@ -4,25 +4,101 @@
 //! module instead.
 use crate::cargo_rustc_link_lib;
 use crate::cargo_rustc_link_search;
+use crate::cfg;
+use crate::conflict::{self, Resolution};
+use crate::error::Error;
+use crate::explain;
+use crate::intercept::{self, Interceptor, Rejection};
+use crate::library;
+#[cfg(feature = "manifest")]
+use crate::manifest;
+use crate::output::{self, OutputFormat};
+use crate::pkg_config;
+use crate::policy::{self, Dedup, Order};
+use crate::probe::{self, Probe};
+use crate::rustc::RustcVersion;
+use crate::rustc_flags;
+use crate::step::{self, BuildStep, Input};
 use crate::utils::VecExt;
-use crate::{Instruction, Value};
+use crate::{Instruction, Prefix, Value};
+use std::panic::{self, AssertUnwindSafe, Location};
 use std::path::PathBuf;
+use std::process::{Command, Output};
 use std::{io, str};
 
+/// An instruction in the stack, and where it was passed from.
+pub(crate) struct Entry {
+    /// The instruction.
+    pub(crate) instruction: Instruction,
+
+    /// Where the instruction was passed from.
+    pub(crate) location: &'static Location<'static>,
+
+    /// The name of the [`BuildStep`](step::BuildStep) which passed the instruction, if any.
+    pub(crate) step: Option<String>,
+}
+
+/// Where a [`BuildScript`](BuildScript) explains instructions.
+enum Explain<'w> {
+    /// Stderr, which is looked up when writing.
+    Stderr,
+
+    /// A writer passed to [`explain_to()`](BuildScript::explain_to).
+    Writer(&'w mut (dyn io::Write + Send)),
+}
+
 /// A build script. This is the main struct for creating cargo arguments.
 /// # Notes
 /// 99% of the time, you won't need this. Instead, use the functions in [`basic`](crate::basic).
 pub struct BuildScript<'w> {
     /// The instruction stack. If `now` is `true`, this will not be used.
-    instructions: Vec<Instruction>,
+    instructions: Vec<Entry>,
 
     /// Whether or not you should write instructions to `writer` immediately.
     now: bool,
 
+    /// Whether or not anything has been written to `writer` yet.
+    written: bool,
+
+    /// How duplicate instructions in the stack are handled on build.
+    dedup: Dedup,
+
+    /// The order the instructions in the stack are written in on build.
+    order: Order,
+
+    /// What to do with conflicting instructions in the stack on build.
+    conflicts: Resolution,
+
+    /// Whether or not the expression of the last [`when()`](Self::when) held, until
+    /// [`otherwise()`](Self::otherwise) is called.
+    last_when: Option<bool>,
+
     /// The writer where instructions will be written.
     /// # Notes
     /// 99% of the time, you can use the defaults, which is [`io::stdout()`](io::stdout).
     writer: &'w mut (dyn io::Write + Send),
+
+    /// Where instructions are explained as they are passed, if enabled.
+    explain: Option<Explain<'w>>,
+
+    /// How code is compiled by probes. If not set, [`Probe::from_env()`](Probe::from_env) is used.
+    probe: Option<Probe>,
+
+    /// Whether or not `RUSTC_BOOTSTRAP` was already tracked by [`probe_feature()`](Self::probe_feature).
+    bootstrap_tracked: bool,
+
+    /// How instructions are rendered when written.
+    format: Box<dyn OutputFormat + Send>,
+
+    /// Every instruction passes through these in order before it is queued or written.
+    interceptors: Vec<Box<dyn Interceptor + Send>>,
+
+    /// The instructions rejected by `interceptors` since the last build.
+    rejections: Vec<Rejection>,
+
+    /// The manifest being recorded, if enabled.
+    #[cfg(feature = "manifest")]
+    manifest: Option<manifest::Recorder>,
 }
 
 impl Default for BuildScript<'static> {
@@ -46,6 +122,23 @@ impl<'w> BuildScript<'w> {
             writer,
             instructions: Vec::new(),
             now: false,
+            written: false,
+            dedup: Dedup::default(),
+            order: Order::default(),
+            conflicts: Resolution::default(),
+            last_when: None,
+            probe: None,
+            bootstrap_tracked: false,
+            format: Box::new(output::CargoLegacy),
+            interceptors: Vec::new(),
+            rejections: Vec::new(),
+            explain: if explain::enabled_by_env() {
+                Some(Explain::Stderr)
+            } else {
+                None
+            },
+            #[cfg(feature = "manifest")]
+            manifest: None,
         }
     }
     /// Sets `now` to true.
@@ -55,6 +148,121 @@ impl<'w> BuildScript<'w> {
         self
     }
 
+    /// Returns `true` if anything has been written to `writer` yet.
+    pub fn has_written(&self) -> bool {
+        self.written
+    }
+
+    /// Explain every instruction passed from now on to stderr: what cargo does with it, which
+    /// targets it affects and where it was passed from. Cargo keeps the stderr of build scripts in
+    /// `target`, so this also works as a log. This is enabled by default if the
+    /// [`BUILD_SCRIPT_EXPLAIN`](explain::ENV) environment variable is `1`, `true`, `yes` or `on`.
+    pub fn explain(&mut self) -> &mut Self {
+        self.explain = Some(Explain::Stderr);
+
+        self
+    }
+
+    /// [`explain()`](Self::explain), but to the specified writer instead of stderr.
+    pub fn explain_to(&mut self, writer: &'w mut (dyn io::Write + Send)) -> &mut Self {
+        self.explain = Some(Explain::Writer(writer));
+
+        self
+    }
+
+    /// Set how instructions are rendered when written. The default is
+    /// [`CargoLegacy`](output::CargoLegacy). See [`output`](output) for the built-in formats.
+    pub fn output_format(&mut self, format: impl OutputFormat + Send + 'static) -> &mut Self {
+        self.format = Box::new(format);
+
+        self
+    }
+
+    /// Add an [`Interceptor`](Interceptor), which sees every instruction passed from now on before
+    /// it is queued or written. Interceptors are called in the order they were added, each seeing
+    /// what the previous ones passed on. See [`intercept`](intercept) for an example.
+    /// # Notes
+    /// Rejected instructions fail every [`try_build()`](Self::try_build), even in `now` mode, until
+    /// they are taken with [`take_rejections()`](Self::take_rejections).
+    pub fn intercept(&mut self, interceptor: impl Interceptor + Send + 'static) -> &mut Self {
+        self.interceptors.push(Box::new(interceptor));
+
+        self
+    }
+
+    /// Pass an instruction through the interceptors, explaining and recording what is left.
+    fn intercepted(
+        &mut self,
+        instruction: Instruction,
+        location: &'static Location<'static>,
+    ) -> Vec<Entry> {
+        let instructions = match intercept::apply(&mut self.interceptors, instruction, location) {
+            Ok(instructions) => instructions,
+            Err(rejection) => {
+                self.rejections.push(*rejection);
+
+                return Vec::new();
+            }
+        };
+
+        instructions
+            .into_iter()
+            .map(|instruction| {
+                self.narrate(&instruction, location);
+
+                Entry {
+                    instruction,
+                    location,
+                    step: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Explain an instruction if enabled.
+    fn narrate(&mut self, instruction: &Instruction, location: &'static Location<'static>) {
+        let mut stderr;
+        let writer: &mut dyn io::Write = match &mut self.explain {
+            Some(Explain::Stderr) => {
+                stderr = io::stderr();
+                &mut stderr
+            }
+            Some(Explain::Writer(writer)) => *writer,
+            None => return,
+        };
+
+        writeln!(
+            writer,
+            "build_script: `{}` (at {})\n    {}",
+            instruction,
+            location,
+            explain::explain(instruction)
+        )
+        .expect("failed to write explanation")
+    }
+
+    /// Set how duplicate instructions in the stack are handled on [`build()`](Self::build).
+    pub fn dedup(&mut self, dedup: Dedup) -> &mut Self {
+        self.dedup = dedup;
+
+        self
+    }
+
+    /// Set the order the instructions in the stack are written in on [`build()`](Self::build).
+    pub fn order(&mut self, order: Order) -> &mut Self {
+        self.order = order;
+
+        self
+    }
+
+    /// Set what to do with conflicting instructions in the stack on [`build()`](Self::build). See
+    /// [`conflict`](conflict) for what is considered a conflict.
+    pub fn conflicts(&mut self, resolution: Resolution) -> &mut Self {
+        self.conflicts = resolution;
+
+        self
+    }
+
     /// Write to `writer`.
     fn write(&mut self, string: &str) {
         /// Newline.
@@ -73,26 +281,556 @@ impl<'w> BuildScript<'w> {
                 .expect("string contained invalid utf8 even if it was already a string before")
         };
 
-        write!(self.writer, "{}", string).expect("failed to write to writer")
+        write!(self.writer, "{}", string).expect("failed to write to writer");
+        self.written = true
+    }
+
+    /// Write an instruction to `writer` in the output format, recording it in the manifest if
+    /// enabled.
+    fn emit(&mut self, instruction: &Instruction) {
+        #[cfg(feature = "manifest")]
+        if let Some(manifest) = &mut self.manifest {
+            manifest.instruction(instruction)
+        }
+
+        if let Some(line) = self.format.format(instruction) {
+            self.write(&line)
+        }
     }
 
     /// Write the instruction immediately if `now` is true, else push it to the instruction stack.
-    fn parse_instruction(&mut self, instruction: Instruction) {
-        if self.now {
-            self.write(&instruction.to_string())
-        } else {
-            self.instructions.push(instruction)
+    fn parse_instruction(
+        &mut self,
+        instruction: Instruction,
+        location: &'static Location<'static>,
+    ) {
+        for entry in self.intercepted(instruction, location) {
+            if self.now {
+                self.emit(&entry.instruction)
+            } else {
+                self.instructions.push(entry)
+            }
         }
     }
 
-    /// Write and remove all the instructions in the stack, starting from the first.
+    /// Write and remove all the instructions in the stack, starting from the first, after applying
+    /// the [`conflicts()`](Self::conflicts), [`dedup()`](Self::dedup) and [`order()`](Self::order)
+    /// policies. If the manifest is enabled, it is written afterwards.
+    /// # Panics
+    /// This panics if [`try_build()`](Self::try_build) fails, which only happens if conflicts are
+    /// set to [`Resolution::Error`](Resolution::Error), an [`Interceptor`](Interceptor) rejected an
+    /// instruction or the manifest couldn't be written. With the defaults, this never panics.
     pub fn build(&mut self) {
-        while let Some(instruction) = self.instructions.take_first() {
-            self.write(&instruction.to_string())
+        if let Err(error) = self.try_build() {
+            panic!("{}", error)
+        }
+    }
+
+    /// [`build()`](Self::build), but errors are returned instead.
+    /// # Errors
+    /// Rejected instructions and conflicts are checked before anything is written, so if they
+    /// fail, nothing is written and the stack is left untouched. The manifest is written last, so
+    /// if that fails, the instructions have already been written and the stack is empty.
+    pub fn try_build(&mut self) -> Result<(), Error> {
+        if !self.rejections.is_empty() {
+            return Err(Error::Rejected(self.rejections.clone()));
+        }
+
+        let origins: Vec<_> = self
+            .instructions
+            .iter()
+            .map(|entry| (&entry.instruction, entry.location))
+            .collect();
+        let (conflicts, losers) = conflict::detect(&origins, self.conflicts);
+
+        match self.conflicts {
+            Resolution::Error if !conflicts.is_empty() => return Err(Error::Conflicts(conflicts)),
+            Resolution::Warn => {
+                for conflict in conflicts {
+                    self.instructions.push(Entry {
+                        instruction: Instruction::new(
+                            "warning",
+                            Value::Singular(conflict.to_string()),
+                        ),
+                        location: conflict.second.location,
+                        step: None,
+                    })
+                }
+            }
+            _ => {}
+        }
+
+        let instructions = std::mem::take(&mut self.instructions)
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| losers.binary_search(index).is_err())
+            .map(|(_, entry)| entry)
+            .collect();
+        self.instructions = policy::apply(
+            instructions,
+            |entry| &entry.instruction,
+            self.dedup,
+            self.order,
+        );
+
+        while let Some(entry) = self.instructions.take_first() {
+            self.emit(&entry.instruction)
+        }
+
+        #[cfg(feature = "manifest")]
+        if let Some(manifest) = &mut self.manifest {
+            manifest.write().map_err(Error::Manifest)?
+        }
+
+        Ok(())
+    }
+
+    /// Run `steps` in order. Their [`inputs()`](BuildStep::inputs) are passed as
+    /// `rerun-if-changed` and `rerun-if-env-changed`, every instruction a step passes is attributed
+    /// to it (see [`attributed()`](Self::attributed)) and its warnings are prefixed with its name.
+    /// # Notes
+    /// A failing step doesn't stop the later ones. The instructions of a failed step are
+    /// discarded, except for its `rerun-if-changed` and `rerun-if-env-changed`, and every failure
+    /// is returned together. In `now` mode, the instructions of a step are written once it is
+    /// done. A step only sees the instructions it passed itself, so removing or taking the
+    /// instructions in the stack doesn't affect those of earlier steps. If a step panics, its
+    /// instructions are discarded, and the stack and `now` mode are restored before the panic
+    /// continues, so the instructions of earlier steps are kept.
+    pub fn run_steps(&mut self, steps: &[&dyn BuildStep]) -> Result<&mut Self, step::Error> {
+        let mut failures = Vec::new();
+        let now = std::mem::replace(&mut self.now, false);
+
+        for step in steps {
+            // each step passes its instructions to its own stack, so it can't touch the others.
+            let outer = std::mem::take(&mut self.instructions);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                for input in step.inputs() {
+                    match input {
+                        Input::File(path) => self.cargo_rerun_if_changed(path),
+                        Input::Env(var) => self.cargo_rerun_if_env_changed(&var),
+                    };
+                }
+
+                step.run(self)
+            }));
+            let mut block = std::mem::replace(&mut self.instructions, outer);
+            let result = match result {
+                Ok(result) => result,
+                Err(payload) => {
+                    self.now = now;
+                    panic::resume_unwind(payload)
+                }
+            };
+
+            if let Err(error) = result {
+                // what the step depends on still decides when it is rerun.
+                block.retain(|entry| {
+                    matches!(
+                        (&entry.instruction.prefix, entry.instruction.name.as_deref()),
+                        (
+                            Prefix::Cargo,
+                            Some("rerun-if-changed") | Some("rerun-if-env-changed")
+                        )
+                    )
+                });
+                failures.push(step::Failure {
+                    step: step.name().into(),
+                    error,
+                })
+            }
+
+            for entry in &mut block {
+                // steps running other steps keep the innermost name.
+                if entry.step.is_some() {
+                    continue;
+                }
+
+                if let (Prefix::Cargo, Some("warning"), Value::Singular(message)) = (
+                    &entry.instruction.prefix,
+                    entry.instruction.name.as_deref(),
+                    &mut entry.instruction.value,
+                ) {
+                    *message = format!("{}: {}", step.name(), message)
+                }
+
+                entry.step = Some(step.name().into())
+            }
+
+            if now {
+                for entry in block {
+                    self.emit(&entry.instruction)
+                }
+            } else {
+                self.instructions.extend(block)
+            }
+        }
+
+        self.now = now;
+
+        if failures.is_empty() {
+            Ok(self)
+        } else {
+            Err(step::Error { failures })
+        }
+    }
+
+    /// Iterate over the instructions in the stack, starting from the first, with the name of the
+    /// [`BuildStep`](BuildStep) which passed each of them, if any.
+    pub fn attributed(&self) -> impl Iterator<Item = (&Instruction, Option<&str>)> {
+        self.instructions
+            .iter()
+            .map(|entry| (&entry.instruction, entry.step.as_deref()))
+    }
+
+    /// Iterate over the instructions in the stack, starting from the first.
+    /// # Notes
+    /// In `now` mode, instructions are written immediately, so the stack is always empty.
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.instructions.iter().map(|entry| &entry.instruction)
+    }
+
+    /// The number of instructions in the stack.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Returns `true` if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Iterate over the instructions in the stack with the specified name, such as
+    /// `rustc-link-lib`.
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Instruction> {
+        self.instructions()
+            .filter(move |instruction| instruction.name.as_deref() == Some(name))
+    }
+
+    /// Remove every instruction in the stack matching `predicate`. Returns the number of
+    /// instructions removed.
+    pub fn remove_if(&mut self, mut predicate: impl FnMut(&Instruction) -> bool) -> usize {
+        let len = self.instructions.len();
+        self.instructions
+            .retain(|entry| !predicate(&entry.instruction));
+
+        len - self.instructions.len()
+    }
+
+    /// Insert an instruction into the stack before the first instruction matching `predicate`.
+    /// Returns `false` if no instruction matched, in which case nothing is inserted.
+    #[track_caller]
+    pub fn insert_before(
+        &mut self,
+        predicate: impl FnMut(&Instruction) -> bool,
+        instruction: Instruction,
+    ) -> bool {
+        self.insert_at(predicate, 0, instruction, Location::caller())
+    }
+
+    /// Insert an instruction into the stack after the first instruction matching `predicate`.
+    /// Returns `false` if no instruction matched, in which case nothing is inserted.
+    #[track_caller]
+    pub fn insert_after(
+        &mut self,
+        predicate: impl FnMut(&Instruction) -> bool,
+        instruction: Instruction,
+    ) -> bool {
+        self.insert_at(predicate, 1, instruction, Location::caller())
+    }
+
+    /// Insert an instruction `offset` places after the first instruction matching `predicate`.
+    fn insert_at(
+        &mut self,
+        predicate: impl FnMut(&Instruction) -> bool,
+        offset: usize,
+        instruction: Instruction,
+        location: &'static Location<'static>,
+    ) -> bool {
+        match self.position(predicate) {
+            Some(index) => {
+                let entries = self.intercepted(instruction, location);
+                self.instructions
+                    .splice(index + offset..index + offset, entries);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the first instruction in the stack matching `predicate`. Returns the replaced
+    /// instruction, or [`None`](None) if no instruction matched.
+    /// # Notes
+    /// The new instruction passes through the interceptors, so it may be replaced by several or
+    /// none.
+    #[track_caller]
+    pub fn replace(
+        &mut self,
+        predicate: impl FnMut(&Instruction) -> bool,
+        instruction: Instruction,
+    ) -> Option<Instruction> {
+        let index = self.position(predicate)?;
+        let entries = self.intercepted(instruction, Location::caller());
+        let mut replaced = self.instructions.splice(index..=index, entries);
+
+        replaced.next().map(|entry| entry.instruction)
+    }
+
+    /// The index of the first instruction in the stack matching `predicate`.
+    fn position(&self, mut predicate: impl FnMut(&Instruction) -> bool) -> Option<usize> {
+        self.instructions
+            .iter()
+            .position(|entry| predicate(&entry.instruction))
+    }
+
+    /// Remove and return every entry in the stack without writing them.
+    pub(crate) fn take_entries(&mut self) -> Vec<Entry> {
+        std::mem::take(&mut self.instructions)
+    }
+
+    /// Pass an entry taken from another build script through the interceptors, keeping where it
+    /// was passed from and by which step.
+    pub(crate) fn push_entry(&mut self, entry: Entry) {
+        for mut intercepted in self.intercepted(entry.instruction, entry.location) {
+            intercepted.step = entry.step.clone();
+
+            if self.now {
+                self.emit(&intercepted.instruction)
+            } else {
+                self.instructions.push(intercepted)
+            }
+        }
+    }
+
+    /// Add the rejections of another build script, so they fail
+    /// [`try_build()`](Self::try_build) here.
+    pub(crate) fn push_rejections(&mut self, rejections: Vec<Rejection>) {
+        self.rejections.extend(rejections)
+    }
+
+    /// A build script which only buffers instructions to pass them on to another one, which
+    /// explains them.
+    pub(crate) fn buffer() -> BuildScript<'static> {
+        let mut build_script = BuildScript::new(Box::leak(Box::new(io::sink())));
+        build_script.explain = None;
+
+        build_script
+    }
+
+    /// Remove and return the instructions rejected by the interceptors, so
+    /// [`try_build()`](Self::try_build) no longer fails because of them.
+    pub fn take_rejections(&mut self) -> Vec<Rejection> {
+        std::mem::take(&mut self.rejections)
+    }
+
+    /// Remove and return every instruction in the stack without writing them. No policies are
+    /// applied.
+    pub fn take_pending(&mut self) -> Vec<Instruction> {
+        self.instructions
+            .drain(..)
+            .map(|entry| entry.instruction)
+            .collect()
+    }
+
+    /// Call `func`, keeping the instructions it passes only if it returns [`Ok`](Ok). If it returns
+    /// [`Err`](Err) or panics, they are discarded, and the error or panic is passed on. Transactions
+    /// can be nested, and an inner transaction which succeeded is still discarded if the outer one
+    /// fails.
+    /// # Notes
+    /// `func` passes its instructions to a stack of its own, which is merged into this one once the
+    /// transaction succeeds, or written in `now` mode. The stack `func` sees therefore only holds
+    /// the instructions it passed itself, and taking or removing them can't affect the ones
+    /// passed before the transaction. Calling [`build()`](Self::build) inside the transaction
+    /// writes what `func` passed so far, which can't be rolled back, so it shouldn't be done.
+    /// # Examples
+    /// ```rust
+    /// use build_script::BuildScript;
+    ///
+    /// let mut build_script = BuildScript::default();
+    /// let result = build_script.transaction(|build_script| {
+    ///     build_script.cargo_rustc_link_search(None, "/opt/ssl/lib".into());
+    ///     build_script.cargo_rustc_link_lib(None, "ssl");
+    ///     std::fs::metadata("/opt/ssl/lib/libcrypto.so")?;
+    ///     build_script.cargo_rustc_link_lib(None, "crypto");
+    ///
+    ///     Ok::<_, std::io::Error>(())
+    /// });
+    ///
+    /// if result.is_err() {
+    ///     assert!(build_script.is_empty());
+    /// }
+    /// ```
+    pub fn transaction<T, E>(
+        &mut self,
+        func: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let outer = std::mem::take(&mut self.instructions);
+        let now = std::mem::replace(&mut self.now, false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| func(self)));
+        let block = std::mem::replace(&mut self.instructions, outer);
+        self.now = now;
+
+        match result {
+            Ok(Ok(value)) => {
+                if now {
+                    for entry in block {
+                        self.emit(&entry.instruction)
+                    }
+                } else {
+                    self.instructions.extend(block)
+                }
+
+                Ok(value)
+            }
+            Ok(Err(error)) => Err(error),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Call `func` only if the `cfg` expression holds for the target being compiled for, such as
+    /// `all(target_os = "linux", target_env = "gnu")`. See [`cfg`](mod@cfg) for how it is
+    /// evaluated.
+    /// # Panics
+    /// This panics if the expression is invalid.
+    /// # Examples
+    /// ```rust
+    /// use build_script::BuildScript;
+    ///
+    /// BuildScript::default()
+    ///     .when(r#"all(target_os = "linux", target_env = "gnu")"#, |build_script| {
+    ///         build_script.cargo_rustc_link_lib(None, "dl");
+    ///     })
+    ///     .otherwise(|build_script| {
+    ///         build_script.cargo_rustc_link_lib(None, "c");
+    ///     })
+    ///     .build();
+    /// ```
+    #[track_caller]
+    pub fn when(&mut self, expr: &str, func: impl FnOnce(&mut Self)) -> &mut Self {
+        self.when_in(expr, func, &|var| std::env::var(var).ok())
+    }
+
+    /// [`when()`](Self::when), reading variables through `var`.
+    #[track_caller]
+    fn when_in(
+        &mut self,
+        expr: &str,
+        func: impl FnOnce(&mut Self),
+        var: &dyn Fn(&str) -> Option<String>,
+    ) -> &mut Self {
+        let expr: cfg::Expr = match expr.parse() {
+            Ok(expr) => expr,
+            Err(error) => panic!("invalid cfg expression `{}`: {}", expr, error),
+        };
+        let holds = expr.eval(var);
+
+        if holds {
+            func(self);
+        }
+
+        self.last_when = Some(holds);
+
+        self
+    }
+
+    /// Pass `name` as `rustc-cfg` if the `cfg` expression holds for the target being compiled for,
+    /// so code can use `#[cfg(name)]` instead of repeating the expression. `name` is always
+    /// declared through `rustc-check-cfg`. See [`cfg`](mod@cfg) for how the expression is
+    /// evaluated.
+    /// # Panics
+    /// This panics if the expression is invalid.
+    /// # Examples
+    /// ```rust
+    /// use build_script::BuildScript;
+    ///
+    /// BuildScript::default()
+    ///     .cfg_alias(
+    ///         "linux_x86_64",
+    ///         r#"all(target_os = "linux", target_arch = "x86_64")"#,
+    ///     )
+    ///     .build();
+    /// ```
+    #[track_caller]
+    pub fn cfg_alias(&mut self, name: &str, expr: &str) -> &mut Self {
+        self.cfg_alias_in(name, expr, &|var| std::env::var(var).ok())
+    }
+
+    /// [`cfg_alias()`](Self::cfg_alias), reading variables through `var`.
+    #[track_caller]
+    fn cfg_alias_in(
+        &mut self,
+        name: &str,
+        expr: &str,
+        var: &dyn Fn(&str) -> Option<String>,
+    ) -> &mut Self {
+        let expr: cfg::Expr = match expr.parse() {
+            Ok(expr) => expr,
+            Err(error) => panic!("invalid cfg expression `{}`: {}", expr, error),
+        };
+        self.cargo_rustc_check_cfg(&format!("cfg({})", name));
+
+        if expr.eval(var) {
+            self.cargo_rustc_cfg(name, None);
+        }
+
+        self
+    }
+
+    /// Call `func` only if the expression of the preceding [`when()`](Self::when) didn't hold.
+    /// # Panics
+    /// This panics if it doesn't follow a [`when()`](Self::when).
+    #[track_caller]
+    pub fn otherwise(&mut self, func: impl FnOnce(&mut Self)) -> &mut Self {
+        match self.last_when.take() {
+            Some(true) => {}
+            Some(false) => func(self),
+            None => panic!("otherwise() must follow when()"),
+        }
+
+        self
+    }
+
+    /// Record a manifest of everything this build script does, and write it to
+    /// [`manifest::FILE_NAME`](manifest::FILE_NAME) in `OUT_DIR` on every
+    /// [`build()`](Self::build). See [`manifest`](manifest) for the format.
+    /// # Panics
+    /// This panics if `OUT_DIR` isn't set, which cargo always sets for build scripts. Failing to
+    /// write the manifest makes [`try_build()`](Self::try_build) return
+    /// [`Error::Manifest`](Error::Manifest) instead.
+    /// # Notes
+    /// This is only available with the `manifest` feature.
+    #[cfg(feature = "manifest")]
+    pub fn manifest(&mut self) -> &mut Self {
+        let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR is not set");
+
+        self.manifest_to(PathBuf::from(out_dir).join(manifest::FILE_NAME))
+    }
+
+    /// [`manifest()`](Self::manifest), but the manifest is written to `path` instead.
+    /// # Notes
+    /// This is only available with the `manifest` feature.
+    #[cfg(feature = "manifest")]
+    pub fn manifest_to(&mut self, path: PathBuf) -> &mut Self {
+        self.manifest = Some(manifest::Recorder::new(path));
+
+        self
+    }
+
+    /// Record a tool invocation in the manifest. If the manifest isn't enabled, this does
+    /// nothing.
+    /// # Notes
+    /// This is only available with the `manifest` feature.
+    #[cfg(feature = "manifest")]
+    pub fn record_invocation(&mut self, invocation: manifest::Invocation) -> &mut Self {
+        if let Some(manifest) = &mut self.manifest {
+            manifest.invocation(invocation)
         }
+
+        self
     }
 
     /// Wrapper for `cargo:rerun-if-changed=PATH`. This tells Cargo when to rerun the script.
+    #[track_caller]
     pub fn cargo_rerun_if_changed(&mut self, path: PathBuf) -> &mut Self {
         let instruction = Instruction::new(
             "rerun-if-changed",
@@ -103,6 +841,7 @@ impl<'w> BuildScript<'w> {
     }
 
     /// Wrapper for `cargo:rerun-if-env-changed=VAR`. This tells Cargo when to rerun the script.
+    #[track_caller]
     pub fn cargo_rerun_if_env_changed(&mut self, var: &str) -> &mut Self {
         let instruction = Instruction::new("rerun-if-env-changed", Value::Singular(var.into()));
 
@@ -110,6 +849,7 @@ impl<'w> BuildScript<'w> {
     }
 
     /// Wrapper for `cargo:rustc-link-lib=[KIND=]NAME`. This adds a library to link.
+    #[track_caller]
     pub fn cargo_rustc_link_lib(
         &mut self,
         kind: Option<cargo_rustc_link_lib::Kind>,
@@ -124,6 +864,7 @@ impl<'w> BuildScript<'w> {
     }
 
     /// Wrapper for `cargo:rustc-link-search=[KIND=]PATH`. This adds to the library search path.
+    #[track_caller]
     pub fn cargo_rustc_link_search(
         &mut self,
         kind: Option<cargo_rustc_link_search::Kind>,
@@ -138,23 +879,387 @@ impl<'w> BuildScript<'w> {
     }
 
     /// Wrapper for `cargo:rustc-flags=FLAGS`. This passes certain flags to the compiler.
+    #[track_caller]
     pub fn cargo_rustc_flags(&mut self, flags: &str) -> &mut Self {
         let instruction = Instruction::new("rustc-flags", Value::Singular(flags.into()));
 
-        self.custom_instruction(instruction)
+        self.custom_instruction(instruction)
+    }
+
+    /// [`cargo_rustc_flags()`](Self::cargo_rustc_flags), but the flags are validated first.
+    /// Instead of `rustc-flags`, the equivalent `rustc-link-lib` and `rustc-link-search`
+    /// instructions are used. If a flag isn't supported by cargo, nothing is added.
+    #[track_caller]
+    pub fn try_cargo_rustc_flags(&mut self, flags: &str) -> Result<&mut Self, rustc_flags::Error> {
+        for flag in rustc_flags::parse(flags)? {
+            self.custom_instruction(flag.instruction());
+        }
+
+        Ok(self)
+    }
+
+    /// Wrapper for `cargo:rustc-cfg=KEY[="VALUE"]`. This enable compile-time `cfg` settings.
+    #[track_caller]
+    pub fn cargo_rustc_cfg(&mut self, key: &str, value: Option<&str>) -> &mut Self {
+        let instruction = Instruction::new(
+            "rustc-cfg",
+            Value::OptionalValue(key.into(), value.map(Into::into)),
+        );
+
+        self.custom_instruction(instruction)
+    }
+
+    /// Wrapper for `cargo:rustc-check-cfg=CHECK_CFG`. This declares the expected `cfg`s, such as
+    /// `cfg(has_foo)` or `cfg(foo, values("a", "b"))`, so the `unexpected_cfgs` lint doesn't warn
+    /// about them.
+    #[track_caller]
+    pub fn cargo_rustc_check_cfg(&mut self, check_cfg: &str) -> &mut Self {
+        let instruction = Instruction::new("rustc-check-cfg", Value::Singular(check_cfg.into()));
+
+        self.custom_instruction(instruction)
+    }
+
+    /// Pass `rustc_1_N` as `rustc-cfg` for every minor version `N` in `minors` the compiler is at
+    /// least as new as, and `nightly` if it is a nightly or dev compiler. All of them are declared
+    /// through `rustc-check-cfg`, whether they are passed or not. See [`rustc`](crate::rustc) for
+    /// an example.
+    #[track_caller]
+    pub fn rustc_version_cfgs(&mut self, version: &RustcVersion, minors: &[u32]) -> &mut Self {
+        for minor in minors {
+            let name = format!("rustc_1_{}", minor);
+            self.cargo_rustc_check_cfg(&format!("cfg({})", name));
+
+            if version.at_least(1, *minor) {
+                self.cargo_rustc_cfg(&name, None);
+            }
+        }
+
+        self.cargo_rustc_check_cfg("cfg(nightly)");
+
+        if version.is_nightly() {
+            self.cargo_rustc_cfg("nightly", None);
+        }
+
+        self
+    }
+
+    /// Set how code is compiled by [`probe_code()`](Self::probe_code) and the other probes. The
+    /// default is [`Probe::from_env()`](Probe::from_env).
+    pub fn probe(&mut self, probe: Probe) -> &mut Self {
+        self.probe = Some(probe);
+
+        self
+    }
+
+    /// Compile `code` as a library crate for the target. If it compiles, `cfg` is passed as
+    /// `rustc-cfg`. Either way, it is declared through `rustc-check-cfg`. Returns `true` if it
+    /// compiles. If the manifest is enabled, the compiler invocation is recorded.
+    /// # Panics
+    /// This panics if no [`probe()`](Self::probe) was set and `OUT_DIR` isn't set.
+    /// # Notes
+    /// If the compiler can't be run at all, the reason is passed as `warning` and `false` is
+    /// returned.
+    #[track_caller]
+    pub fn probe_code(&mut self, cfg: &str, code: &str) -> bool {
+        let result = self.run_probe(code, |_| {});
+        self.cargo_rustc_check_cfg(&format!("cfg({})", cfg));
+
+        match result {
+            Ok(output) if output.status.success() => {
+                self.cargo_rustc_cfg(cfg, None);
+
+                true
+            }
+            Ok(_) => false,
+            Err(error) => {
+                self.cargo_warning(&format!("failed to probe `{}`: {}", cfg, error));
+
+                false
+            }
+        }
+    }
+
+    /// Compile `code` with the probe, after `configure` is called with the compiler command.
+    /// Records the invocation if the manifest is enabled.
+    fn run_probe(
+        &mut self,
+        code: &str,
+        configure: impl FnOnce(&mut Command),
+    ) -> io::Result<Output> {
+        let probe = self.probe.get_or_insert_with(Probe::from_env).clone();
+        #[cfg(feature = "manifest")]
+        let mut invocation = None;
+        let result = probe.compile(code, |command| {
+            configure(command);
+
+            #[cfg(feature = "manifest")]
+            {
+                let (output, recorded) = manifest::Invocation::output(command)?;
+                invocation = Some(recorded);
+
+                Ok(output)
+            }
+
+            #[cfg(not(feature = "manifest"))]
+            command.output()
+        });
+
+        #[cfg(feature = "manifest")]
+        if let Some(invocation) = invocation {
+            self.record_invocation(invocation);
+        }
+
+        result
+    }
+
+    /// Check whether the feature gate `gate`, such as `portable_simd`, can be enabled through
+    /// `#![feature(...)]`. If it can, `feature_<gate>` is passed as `rustc-cfg`. Either way, it is
+    /// declared through `rustc-check-cfg`, and `RUSTC_BOOTSTRAP` is tracked through
+    /// `rerun-if-env-changed` (once). Returns `true` if the gate can be enabled.
+    /// # Notes
+    /// `RUSTC_BOOTSTRAP` is honored, including when it lists crate names, in which case it applies
+    /// if `CARGO_PKG_NAME` (with `-` replaced by `_`) is listed. If the gate was stabilized, the
+    /// `stable_features` warning of the compiler is passed as `warning`. If a gate which could be
+    /// enabled the last time it was probed with the same `OUT_DIR` no longer can, such as because
+    /// it was removed, the compiler error is passed as `warning`.
+    /// # Panics
+    /// This panics if no [`probe()`](Self::probe) was set and `OUT_DIR` isn't set.
+    #[track_caller]
+    pub fn probe_feature(&mut self, gate: &str) -> bool {
+        self.probe_feature_in(gate, &|name| std::env::var(name).ok())
+    }
+
+    /// [`probe_feature()`](Self::probe_feature), with the environment variables from `var`.
+    #[track_caller]
+    fn probe_feature_in(&mut self, gate: &str, var: &dyn Fn(&str) -> Option<String>) -> bool {
+        let cfg = format!("feature_{}", gate);
+        let crate_name = var("CARGO_PKG_NAME").unwrap_or_default().replace('-', "_");
+        let bootstrap = probe::bootstrap(var("RUSTC_BOOTSTRAP").as_deref(), &crate_name);
+
+        if !std::mem::replace(&mut self.bootstrap_tracked, true) {
+            self.cargo_rerun_if_env_changed("RUSTC_BOOTSTRAP");
+        }
+
+        let result = self.run_probe(&probe::feature(gate), |command| {
+            // the other lints are capped, but this one is how a stabilized gate is noticed.
+            command.args(["--force-warn", "stable-features"]);
+
+            match bootstrap {
+                Some(value) => command.env("RUSTC_BOOTSTRAP", value),
+                None => command.env_remove("RUSTC_BOOTSTRAP"),
+            };
+        });
+        self.cargo_rustc_check_cfg(&format!("cfg({})", cfg));
+        let output = match result {
+            Ok(output) => output,
+            Err(error) => {
+                self.cargo_warning(&format!("failed to probe `{}`: {}", cfg, error));
+
+                return false;
+            }
+        };
+        let works = output.status.success();
+        let out_dir = self
+            .probe
+            .as_ref()
+            .expect("the probe was set when it ran")
+            .out_dir
+            .clone();
+
+        if works {
+            self.cargo_rustc_cfg(&cfg, None);
+
+            if let Some(warning) = probe::stabilized(&output.stderr, gate) {
+                self.cargo_warning(&format!(
+                    "the feature gate `{}` was stabilized, so it no longer needs to be enabled: {}",
+                    gate, warning
+                ));
+            }
+        } else if probe::worked_before(&out_dir, gate) {
+            self.cargo_warning(&format!(
+                "the feature gate `{}` could be enabled before, but now fails: {}",
+                gate,
+                probe::first_error(&output.stderr)
+            ));
+        }
+
+        if let Err(error) = probe::remember(&out_dir, gate, works) {
+            self.cargo_warning(&format!("failed to remember `{}`: {}", cfg, error));
+        }
+
+        works
+    }
+
+    /// [`probe_code()`](Self::probe_code) with an expression, such as `std::hint::black_box(1)`.
+    #[track_caller]
+    pub fn probe_expression(&mut self, cfg: &str, expr: &str) -> bool {
+        self.probe_code(cfg, &probe::expression(expr))
+    }
+
+    /// [`probe_code()`](Self::probe_code) with a type, such as `i128`.
+    #[track_caller]
+    pub fn probe_type(&mut self, cfg: &str, ty: &str) -> bool {
+        self.probe_code(cfg, &probe::ty(ty))
+    }
+
+    /// [`probe_code()`](Self::probe_code) with a path which can be imported, such as
+    /// `std::sync::OnceLock`.
+    #[track_caller]
+    pub fn probe_path(&mut self, cfg: &str, path: &str) -> bool {
+        self.probe_code(cfg, &probe::path(path))
+    }
+
+    /// [`probe_code()`](Self::probe_code) with a trait, such as `core::error::Error`.
+    #[track_caller]
+    pub fn probe_trait(&mut self, cfg: &str, name: &str) -> bool {
+        self.probe_code(cfg, &probe::trait_(name))
+    }
+
+    /// Resolve a library through its pkg-config `.pc` file, with
+    /// [`pkg_config::Config::from_env()`](pkg_config::Config::from_env), and link it through
+    /// [`link_pkg_config()`](Self::link_pkg_config). `constraint` is a version constraint such as
+    /// `>= 1.1`. The environment variables affecting the result are tracked through
+    /// `rerun-if-env-changed`, even if it fails.
+    #[track_caller]
+    pub fn pkg_config(
+        &mut self,
+        name: &str,
+        constraint: Option<&str>,
+    ) -> Result<pkg_config::Library, pkg_config::Error> {
+        for var in pkg_config::env_vars() {
+            self.cargo_rerun_if_env_changed(&var);
+        }
+
+        let library = pkg_config::Config::from_env()?.probe(name, constraint)?;
+        self.link_pkg_config(&library);
+
+        Ok(library)
+    }
+
+    /// Link a resolved library: its `.pc` files through `rerun-if-changed`, its link paths
+    /// through `rustc-link-search` with the `native` kind, its libraries through `rustc-link-lib`
+    /// and its include paths as the `include` metadata (joined like `PATH`), which the build
+    /// scripts of dependent packages can read as `DEP_<LINKS>_INCLUDE`. Libraries given by file
+    /// name, such as `-l:libfoo.a`, are linked through the `+verbatim` modifier, as `static` if
+    /// they are archives and `dylib` otherwise. Every other flag of `Libs`, such as `-pthread`, is
+    /// passed through `rustc-link-arg`, which cargo doesn't apply to library targets.
+    #[track_caller]
+    pub fn link_pkg_config(&mut self, library: &pkg_config::Library) -> &mut Self {
+        for file in &library.files {
+            self.cargo_rerun_if_changed(file.clone());
+        }
+
+        for path in &library.link_paths {
+            self.cargo_rustc_link_search(Some(cargo_rustc_link_search::Kind::Native), path.clone());
+        }
+
+        for lib in &library.libs {
+            match lib.split_once('=') {
+                Some(("framework", name)) => {
+                    self.cargo_rustc_link_lib(Some(cargo_rustc_link_lib::Kind::Framework), name)
+                }
+                _ => match lib.strip_prefix(':') {
+                    Some(file_name) => {
+                        let kind = if file_name.ends_with(".a") {
+                            "static:+verbatim"
+                        } else {
+                            "dylib:+verbatim"
+                        };
+                        let instruction = Instruction::new(
+                            "rustc-link-lib",
+                            Value::UnquotedOptionalKey(Some(kind.into()), file_name.into()),
+                        );
+
+                        self.custom_instruction(instruction)
+                    }
+                    None => self.cargo_rustc_link_lib(None, lib),
+                },
+            };
+        }
+
+        for arg in &library.link_args {
+            self.custom_instruction(Instruction::new(
+                "rustc-link-arg",
+                Value::Singular(arg.clone()),
+            ));
+        }
+
+        if let Ok(include) = std::env::join_paths(&library.include_paths) {
+            if !library.include_paths.is_empty() {
+                self.cargo_mapping("include", &include.to_string_lossy());
+            }
+        }
+
+        self
+    }
+
+    /// [`find_library_with()`](Self::find_library_with) with
+    /// [`library::Search::from_env()`](library::Search::from_env).
+    #[track_caller]
+    pub fn find_library(&mut self, name: &str) -> Result<PathBuf, library::Error> {
+        self.find_library_with(name, &library::Search::from_env())
     }
 
-    /// Wrapper for `cargo:rustc-cfg=KEY[="VALUE"]`. This enable compile-time `cfg` settings.
-    pub fn cargo_rustc_cfg(&mut self, key: &str, value: Option<&str>) -> &mut Self {
-        let instruction = Instruction::new(
-            "rustc-cfg",
-            Value::OptionalValue(key.into(), value.map(Into::into)),
-        );
+    /// Find the file of a native library, such as `libssl.a` or `libssl.so.3` for `ssl`, and link
+    /// it. The link paths in the stack (searched by the linker) are searched first, then the
+    /// directories of `search`, and the first directory holding the library wins. Its directory
+    /// is added through `rustc-link-search` with the `native` kind, unless it already is in the
+    /// stack or is a standard directory, and the library through `rustc-link-lib` with the kind
+    /// picked by the preference of `search`, or without a kind for a `.lib` file of MSVC, which
+    /// can be a static or an import library. A dynamic library which only exists
+    /// with a version, such as `libssl.so.3`, is linked by its file name through the `+verbatim`
+    /// modifier. Returns the file, for further checks.
+    /// # Notes
+    /// In `now` mode, the stack is always empty, so only the directories of `search` are searched.
+    #[track_caller]
+    pub fn find_library_with(
+        &mut self,
+        name: &str,
+        search: &library::Search,
+    ) -> Result<PathBuf, library::Error> {
+        let link_paths: Vec<PathBuf> = self
+            .instructions()
+            .filter(|instruction| instruction.name.as_deref() == Some("rustc-link-search"))
+            .filter_map(|instruction| match &instruction.value {
+                Value::UnquotedOptionalKey(None, path) => Some(path.into()),
+                Value::UnquotedOptionalKey(Some(kind), path)
+                    if kind == "native" || kind == "all" =>
+                {
+                    Some(path.into())
+                }
+                _ => None,
+            })
+            .collect();
+        let found = search.find(name, &link_paths)?;
+        let dir = found
+            .path
+            .parent()
+            .expect("a library is always in a directory")
+            .to_path_buf();
 
-        self.custom_instruction(instruction)
+        if !link_paths.contains(&dir) && !search.standard_dirs.contains(&dir) {
+            self.cargo_rustc_link_search(Some(cargo_rustc_link_search::Kind::Native), dir);
+        }
+
+        if found.verbatim {
+            let file_name = found.path.file_name().expect("a library has a file name");
+            let instruction = Instruction::new(
+                "rustc-link-lib",
+                Value::UnquotedOptionalKey(
+                    Some("dylib:+verbatim".into()),
+                    file_name.to_string_lossy().into(),
+                ),
+            );
+            self.custom_instruction(instruction);
+        } else {
+            self.cargo_rustc_link_lib(found.kind, name);
+        }
+
+        Ok(found.path)
     }
 
     /// Wrapper for `cargo:rustc-env=VAR=VALUE`. This sets an environment variable.
+    #[track_caller]
     pub fn cargo_rustc_env(&mut self, var: &str, value: &str) -> &mut Self {
         let instruction = Instruction::new(
             "rustc-env",
@@ -166,6 +1271,7 @@ impl<'w> BuildScript<'w> {
 
     /// Wrapper for `cargo:rustc-cdylib-link-arg=FLAG`. This passes custom flags to a linker for
     /// cdylib crates.
+    #[track_caller]
     pub fn cargo_rustc_cdylib_link_arg(&mut self, flag: &str) -> &mut Self {
         let instruction = Instruction::new("rustc-cdylib-link-arg", Value::Singular(flag.into()));
 
@@ -173,13 +1279,25 @@ impl<'w> BuildScript<'w> {
     }
 
     /// Wrapper for `cargo:warning=MESSAGE`. This displays a warning on the terminal.
+    #[track_caller]
     pub fn cargo_warning(&mut self, message: &str) -> &mut Self {
         let instruction = Instruction::new("warning", Value::Singular(message.into()));
 
         self.custom_instruction(instruction)
     }
 
+    /// Wrapper for `cargo::error=MESSAGE`. This displays an error on the terminal and fails the
+    /// build once the build script is done. Only [`CargoModern`](output::CargoModern) supports
+    /// it, [`CargoLegacy`](output::CargoLegacy) writes it as a warning instead.
+    #[track_caller]
+    pub fn cargo_error(&mut self, message: &str) -> &mut Self {
+        let instruction = Instruction::new("error", Value::Singular(message.into()));
+
+        self.custom_instruction(instruction)
+    }
+
     /// Wrapper for `cargo:KEY=VALUE`. This is metadata, used by `links` scripts.
+    #[track_caller]
     pub fn cargo_mapping(&mut self, key: &str, value: &str) -> &mut Self {
         let instruction =
             Instruction::new_mapping(Value::UnquotedMapping(key.into(), value.into()));
@@ -189,8 +1307,9 @@ impl<'w> BuildScript<'w> {
 
     /// Pass a custom instruction. Internally, [`BuildScript`](Self) uses this. This may be used
     /// when `build_script` isn't updated for new instructions yet in the future.
+    #[track_caller]
     pub fn custom_instruction(&mut self, instruction: Instruction) -> &mut Self {
-        self.parse_instruction(instruction);
+        self.parse_instruction(instruction, Location::caller());
 
         self
     }
@@ -200,6 +1319,7 @@ impl<'w> BuildScript<'w> {
 mod tests {
     use super::BuildScript;
     use crate::{Instruction, Value};
+    use std::panic::{self, AssertUnwindSafe};
 
     fn parse_bytes_to_lines(bytes: &[u8]) -> Vec<String> {
         let bytes = String::from_utf8_lossy(bytes).to_string();
@@ -244,6 +1364,129 @@ mod tests {
         assert_eq!(lines, expected)
     }
 
+    #[test]
+    fn test_policies() {
+        use crate::policy::{Dedup, Order};
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .dedup(Dedup::KeepFirst)
+            .order(Order::Canonical)
+            .cargo_rustc_link_lib(None, "ssl")
+            .cargo_rustc_cfg("ossl", None)
+            .cargo_rerun_if_changed("build.rs".into())
+            .cargo_rustc_cfg("ossl", None)
+            .build();
+        let output = parse_bytes_to_lines(&writer);
+        let expected = vec![
+            "cargo:rerun-if-changed=build.rs",
+            "cargo:rustc-cfg=ossl",
+            "cargo:rustc-link-lib=ssl",
+        ];
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn test_conflicts() {
+        use crate::cargo_rustc_link_lib::Kind;
+        use crate::conflict::Resolution;
+        use crate::error::Error;
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .conflicts(Resolution::Error)
+            .cargo_rustc_link_lib(Kind::Static.into(), "z");
+        let line = line!() + 1;
+        build_script.cargo_rustc_link_lib(Kind::DynamicLibrary.into(), "z");
+        let conflicts = match build_script.try_build() {
+            Err(Error::Conflicts(conflicts)) => conflicts,
+            _ => panic!("expected conflicts"),
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].second.location.file(), file!());
+        assert_eq!(conflicts[0].second.location.line(), line);
+        build_script.conflicts(Resolution::LastWins).build();
+        let output = parse_bytes_to_lines(&writer);
+        assert_eq!(output, vec!["cargo:rustc-link-lib=dylib=z"])
+    }
+
+    #[test]
+    fn test_conflicts_warn() {
+        use crate::conflict::Resolution;
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .conflicts(Resolution::Warn)
+            .cargo_rustc_env("VERSION", "1")
+            .cargo_rustc_env("VERSION", "2")
+            .build();
+        let output = parse_bytes_to_lines(&writer);
+        assert_eq!(output.len(), 3);
+        assert!(output[2]
+            .starts_with("cargo:warning=conflicting instructions: `cargo:rustc-env=VERSION=1`"))
+    }
+
+    #[test]
+    fn test_inspection() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        assert!(build_script.is_empty());
+        build_script
+            .cargo_rustc_link_lib(None, "ssl")
+            .cargo_rerun_if_changed("build.rs".into())
+            .cargo_rustc_link_lib(None, "crypto");
+        assert_eq!(build_script.len(), 3);
+        let libraries: Vec<_> = build_script
+            .find("rustc-link-lib")
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            libraries,
+            vec!["cargo:rustc-link-lib=ssl", "cargo:rustc-link-lib=crypto"]
+        );
+        assert_eq!(build_script.instructions().count(), 3)
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .cargo_rustc_link_lib(None, "ssl")
+            .cargo_rustc_link_search(None, "/usr/local/lib".into())
+            .cargo_rerun_if_changed("build.rs".into());
+        let is_search = |i: &Instruction| i.name.as_deref() == Some("rustc-link-search");
+        let is_link = |i: &Instruction| i.name.as_deref() == Some("rustc-link-lib");
+        let warning = Instruction::new("warning", Value::Singular("hi".into()));
+        assert_eq!(build_script.remove_if(is_search), 1);
+        assert!(!build_script.insert_before(is_search, warning.clone()));
+        assert!(build_script.insert_before(is_link, warning.clone()));
+        assert!(build_script.insert_after(is_link, warning));
+        let crypto = Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(None, "crypto".into()),
+        );
+        let replaced = build_script.replace(is_link, crypto).unwrap();
+        assert_eq!(replaced.to_string(), "cargo:rustc-link-lib=ssl");
+        let pending: Vec<_> = build_script
+            .take_pending()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:warning=hi",
+            "cargo:rustc-link-lib=crypto",
+            "cargo:warning=hi",
+            "cargo:rerun-if-changed=build.rs",
+        ];
+        assert_eq!(pending, expected);
+        build_script.build();
+        assert!(writer.is_empty())
+    }
+
     #[test]
     fn test_cargo_rerun_if_changed() {
         let mut writer = Vec::new();
@@ -325,6 +1568,24 @@ mod tests {
         assert_eq!(output, expected)
     }
 
+    #[test]
+    fn test_try_cargo_rustc_flags() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .try_cargo_rustc_flags("-lffi -L native=/opt/lib")
+            .unwrap();
+        assert!(build_script.try_cargo_rustc_flags("-l z -g").is_err());
+        build_script.build();
+        let output = parse_bytes_to_lines(&writer);
+        let expected = vec![
+            "cargo:rustc-link-lib=ffi",
+            "cargo:rustc-link-search=native=/opt/lib",
+        ];
+
+        assert_eq!(output, expected)
+    }
+
     #[test]
     fn test_cargo_rustc_cfg() {
         let mut writer = Vec::new();
@@ -394,4 +1655,650 @@ mod tests {
 
         assert_eq!(output, expected)
     }
+
+    #[test]
+    fn test_when() {
+        let var = |var: &str| match var {
+            "CARGO_CFG_TARGET_OS" => Some("linux".to_string()),
+            _ => None,
+        };
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .when_in(
+                r#"target_os = "linux""#,
+                |build_script| {
+                    build_script.cargo_rustc_link_lib(None, "dl");
+                },
+                &var,
+            )
+            .otherwise(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "c");
+            })
+            .when_in(
+                r#"not(target_os = "linux")"#,
+                |build_script| {
+                    build_script.cargo_rustc_link_lib(None, "ws2_32");
+                },
+                &var,
+            )
+            .otherwise(|build_script| {
+                build_script.cargo_rustc_cfg("fallback", None);
+            });
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            pending,
+            vec!["cargo:rustc-link-lib=dl", "cargo:rustc-cfg=fallback"]
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cfg expression")]
+    fn test_when_invalid() {
+        BuildScript::new(&mut Vec::new()).when("all(unix", |_| {});
+    }
+
+    #[test]
+    fn test_run_steps() {
+        use crate::step::{BuildStep, Input};
+        use std::error::Error;
+
+        struct Step(&'static str, bool);
+
+        impl BuildStep for Step {
+            fn name(&self) -> &str {
+                self.0
+            }
+
+            fn inputs(&self) -> Vec<Input> {
+                vec![Input::Env(self.0.to_uppercase())]
+            }
+
+            fn run(
+                &self,
+                build_script: &mut BuildScript,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                build_script.cargo_rustc_link_lib(None, self.0);
+                build_script.cargo_warning("linked");
+
+                if self.1 {
+                    Ok(())
+                } else {
+                    Err("not found".into())
+                }
+            }
+        }
+
+        struct Taking;
+
+        impl BuildStep for Taking {
+            fn name(&self) -> &str {
+                "taking"
+            }
+
+            fn run(
+                &self,
+                build_script: &mut BuildScript,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                build_script.cargo_rustc_link_lib(None, "taken");
+                assert_eq!(build_script.take_pending().len(), 1);
+                build_script.remove_if(|_| true);
+
+                Ok(())
+            }
+        }
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.cargo_rustc_cfg("before", None);
+        let error = build_script
+            .run_steps(&[
+                &Step("ssl", true),
+                &Step("z", false),
+                &Taking,
+                &Step("ffi", true),
+            ])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "1 build step(s) failed\nstep `z` failed: not found"
+        );
+        let attributed: Vec<_> = build_script
+            .attributed()
+            .map(|(instruction, step)| (instruction.to_string(), step))
+            .collect();
+        let expected = vec![
+            ("cargo:rustc-cfg=before".to_string(), None),
+            ("cargo:rerun-if-env-changed=SSL".into(), Some("ssl")),
+            ("cargo:rustc-link-lib=ssl".into(), Some("ssl")),
+            ("cargo:warning=ssl: linked".into(), Some("ssl")),
+            ("cargo:rerun-if-env-changed=Z".into(), Some("z")),
+            ("cargo:rerun-if-env-changed=FFI".into(), Some("ffi")),
+            ("cargo:rustc-link-lib=ffi".into(), Some("ffi")),
+            ("cargo:warning=ffi: linked".into(), Some("ffi")),
+        ];
+        assert_eq!(attributed, expected)
+    }
+
+    #[test]
+    fn test_run_steps_panic() {
+        use crate::step::BuildStep;
+        use std::error::Error;
+
+        struct Panicking;
+
+        impl BuildStep for Panicking {
+            fn name(&self) -> &str {
+                "panicking"
+            }
+
+            fn run(
+                &self,
+                build_script: &mut BuildScript,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                build_script.cargo_rustc_link_lib(None, "lost");
+                panic!("failed")
+            }
+        }
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.cargo_rustc_cfg("before", None);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = build_script.run_steps(&[&Panicking]);
+        }));
+        assert!(result.is_err());
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(pending, vec!["cargo:rustc-cfg=before"]);
+
+        build_script.build();
+        build_script.now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = build_script.run_steps(&[&Panicking]);
+        }));
+        assert!(result.is_err());
+        build_script.cargo_rustc_cfg("after", None);
+        assert!(build_script.is_empty());
+        drop(build_script);
+        assert_eq!(
+            String::from_utf8(writer).unwrap(),
+            "cargo:rustc-cfg=before\ncargo:rustc-cfg=after\n"
+        )
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut explanation = Vec::new();
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.explain_to(&mut explanation);
+        let line = line!() + 1;
+        build_script.cargo_rerun_if_env_changed("ZLIB_DIR");
+        drop(build_script);
+        let expected = format!(
+            "build_script: `cargo:rerun-if-env-changed=ZLIB_DIR` (at {}:{}:22)\n    Cargo \
+             reruns the build script when the environment variable `ZLIB_DIR` changes. Affects \
+             the build script.\n",
+            file!(),
+            line
+        );
+        assert_eq!(String::from_utf8(explanation).unwrap(), expected)
+    }
+
+    #[test]
+    fn test_transaction() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.cargo_rustc_link_lib(None, "before");
+        let result = build_script.transaction(|build_script| {
+            build_script.cargo_rustc_link_search(None, "/opt/ssl/lib".into());
+            build_script.transaction(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "ssl");
+
+                Ok::<_, &str>(())
+            })?;
+            let crypto = build_script.transaction(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "crypto");
+
+                Err::<(), _>("crypto not found")
+            });
+            assert_eq!(crypto, Err("crypto not found"));
+
+            Ok::<_, &str>(())
+        });
+        assert_eq!(result, Ok(()));
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            pending,
+            vec![
+                "cargo:rustc-link-lib=before",
+                "cargo:rustc-link-search=/opt/ssl/lib",
+                "cargo:rustc-link-lib=ssl",
+            ]
+        );
+        let result = build_script.transaction(|build_script| {
+            build_script.transaction(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "z");
+
+                Ok::<_, &str>(())
+            })?;
+
+            Err::<(), _>("failed later")
+        });
+        assert_eq!(result, Err("failed later"));
+        assert_eq!(build_script.len(), 3)
+    }
+
+    #[test]
+    fn test_transaction_take_pending() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.cargo_warning("a").cargo_warning("b");
+        let result = build_script.transaction(|build_script| {
+            assert!(build_script.take_pending().is_empty());
+            build_script.cargo_warning("c");
+
+            Err::<(), _>("failed")
+        });
+        assert_eq!(result, Err("failed"));
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(pending, vec!["cargo:warning=a", "cargo:warning=b"])
+    }
+
+    #[test]
+    fn test_transaction_panic() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            build_script.transaction(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "ssl");
+                panic!("failed");
+                #[allow(unreachable_code)]
+                Ok::<_, ()>(())
+            })
+        }));
+        assert!(result.is_err());
+        assert!(build_script.is_empty())
+    }
+
+    #[test]
+    fn test_transaction_now() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.now();
+        build_script
+            .transaction(|build_script| {
+                build_script.cargo_rustc_link_lib(None, "ssl");
+                assert!(!build_script.has_written());
+
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        let _ = build_script.transaction(|build_script| {
+            build_script.cargo_rustc_link_lib(None, "crypto");
+
+            Err::<(), _>(())
+        });
+        build_script.cargo_rustc_link_lib(None, "z");
+        assert!(build_script.is_empty());
+        drop(build_script);
+        assert_eq!(
+            parse_bytes_to_lines(&writer),
+            vec!["cargo:rustc-link-lib=ssl", "cargo:rustc-link-lib=z"]
+        )
+    }
+
+    #[test]
+    fn test_intercept() {
+        use crate::intercept::Action;
+        use crate::Error;
+
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .intercept(|instruction: Instruction| match &instruction.value {
+                Value::UnquotedOptionalKey(kind, path) if path.starts_with("/repo/") => {
+                    Action::Pass(Instruction::new(
+                        "rustc-link-search",
+                        Value::UnquotedOptionalKey(kind.clone(), path.replace("/repo/", "../")),
+                    ))
+                }
+                Value::UnquotedOptionalKey(_, path) if path.starts_with("/usr/local") => {
+                    Action::Drop
+                }
+                _ => Action::Pass(instruction),
+            })
+            .intercept({
+                let log = log.clone();
+
+                move |instruction: Instruction| {
+                    log.lock().unwrap().push(instruction.to_string());
+
+                    match instruction.name.as_deref() {
+                        Some("rustc-flags") => Action::Reject("rustc-flags is forbidden".into()),
+                        _ => Action::Pass(instruction),
+                    }
+                }
+            });
+        build_script.cargo_rustc_link_search(None, "/repo/lib".into());
+        build_script.cargo_rustc_link_search(None, "/usr/local/lib".into());
+        build_script.cargo_rustc_flags("-l ssl");
+        build_script.cargo_rustc_link_lib(None, "ssl");
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            pending,
+            vec!["cargo:rustc-link-search=../lib", "cargo:rustc-link-lib=ssl"]
+        );
+        match build_script.try_build() {
+            Err(Error::Rejected(rejections)) => {
+                assert_eq!(rejections.len(), 1);
+                assert_eq!(rejections[0].reason, "rustc-flags is forbidden")
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(build_script.len(), 2);
+        assert!(build_script.try_build().is_err());
+        assert_eq!(build_script.take_rejections().len(), 1);
+        build_script.build();
+        drop(build_script);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "cargo:rustc-link-search=../lib",
+                "cargo:rustc-flags=-l ssl",
+                "cargo:rustc-link-lib=ssl",
+            ]
+        );
+        assert_eq!(parse_bytes_to_lines(&writer).len(), 2)
+    }
+
+    #[test]
+    fn test_output_format() {
+        use crate::output::CargoModern;
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.output_format(CargoModern);
+        build_script.cargo_rustc_link_lib(None, "z");
+        build_script.build();
+        assert_eq!(
+            parse_bytes_to_lines(&writer),
+            vec!["cargo::rustc-link-lib=z"]
+        )
+    }
+
+    #[test]
+    fn test_rustc_version_cfgs() {
+        use crate::rustc::RustcVersion;
+
+        let version =
+            RustcVersion::parse("release: 1.80.0-beta.2\nhost: x86_64-unknown-linux-gnu\n")
+                .unwrap();
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.rustc_version_cfgs(&version, &[70, 80, 85]);
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:rustc-check-cfg=cfg(rustc_1_70)",
+            "cargo:rustc-cfg=rustc_1_70",
+            "cargo:rustc-check-cfg=cfg(rustc_1_80)",
+            "cargo:rustc-cfg=rustc_1_80",
+            "cargo:rustc-check-cfg=cfg(rustc_1_85)",
+            "cargo:rustc-check-cfg=cfg(nightly)",
+        ];
+        assert_eq!(pending, expected)
+    }
+
+    #[test]
+    fn test_probes() {
+        use crate::probe::Probe;
+        use crate::rustc::Rustc;
+        use crate::utils::TempDir;
+
+        let out_dir = TempDir::new("probes");
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.probe(Probe {
+            rustc: Rustc::new("rustc"),
+            target: None,
+            rustflags: Vec::new(),
+            out_dir: out_dir.path().into(),
+        });
+        assert!(build_script.probe_expression("has_black_box", "std::hint::black_box(1)"));
+        assert!(!build_script.probe_type("has_u256", "u256"));
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:rustc-check-cfg=cfg(has_black_box)",
+            "cargo:rustc-cfg=has_black_box",
+            "cargo:rustc-check-cfg=cfg(has_u256)",
+        ];
+        assert_eq!(pending, expected);
+        assert_eq!(std::fs::read_dir(out_dir.path()).unwrap().count(), 0);
+
+        // the real compiler warns about a stabilized gate even though lints are capped.
+        let bootstrap = |name: &str| Some("1".to_string()).filter(|_| name == "RUSTC_BOOTSTRAP");
+        build_script.take_pending();
+        assert!(build_script.probe_feature_in("i128_type", &bootstrap));
+        assert!(build_script
+            .find("warning")
+            .any(|warning| warning.to_string().contains("`i128_type` was stabilized")))
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_probe_feature() {
+        use crate::probe::Probe;
+        use crate::rustc::tests::fake_rustc;
+        use crate::utils::TempDir;
+
+        let out_dir = TempDir::new("probe_feature");
+        // accepts gates only while `RUSTC_BOOTSTRAP` is passed as `1`, and `i128_type` is stable.
+        let script = r#"[ "$RUSTC_BOOTSTRAP" = "1" ] || {
+echo "error[E0554]: #![feature] may not be used on the stable release channel" >&2
+exit 1
+}
+for arg in "$@"; do case "$arg" in *probe.rs) source="$arg";; esac; done
+case "$*" in *"--force-warn stable-features"*) grep -q i128_type "$source" && echo "warning: \
+the feature \`i128_type\` has been stable since 1.26.0 and no longer requires an attribute to \
+enable" >&2;; esac
+exit 0"#;
+        let probe = Probe {
+            rustc: fake_rustc(&out_dir.path().join("bin"), "", script),
+            target: None,
+            rustflags: Vec::new(),
+            out_dir: out_dir.path().into(),
+        };
+        let probe_feature = |gates: &[&str], bootstrap: &str| {
+            let var = |name: &str| match name {
+                "RUSTC_BOOTSTRAP" => Some(bootstrap.to_string()),
+                "CARGO_PKG_NAME" => Some("my-crate".to_string()),
+                _ => None,
+            };
+            let mut writer = Vec::new();
+            let mut build_script = BuildScript::new(&mut writer);
+            build_script.probe(probe.clone());
+            let works: Vec<_> = gates
+                .iter()
+                .map(|gate| build_script.probe_feature_in(gate, &var))
+                .collect();
+            let pending: Vec<_> = build_script
+                .instructions()
+                .map(ToString::to_string)
+                .collect();
+
+            (works, pending)
+        };
+
+        let (works, pending) = probe_feature(&["portable_simd", "i128_type"], "other,my_crate");
+        assert_eq!(works, vec![true, true]);
+        assert_eq!(
+            pending,
+            vec![
+                "cargo:rerun-if-env-changed=RUSTC_BOOTSTRAP",
+                "cargo:rustc-check-cfg=cfg(feature_portable_simd)",
+                "cargo:rustc-cfg=feature_portable_simd",
+                "cargo:rustc-check-cfg=cfg(feature_i128_type)",
+                "cargo:rustc-cfg=feature_i128_type",
+                "cargo:warning=the feature gate `i128_type` was stabilized, so it no longer needs \
+                 to be enabled: warning: the feature `i128_type` has been stable since 1.26.0 and \
+                 no longer requires an attribute to enable",
+            ]
+        );
+        let (works, pending) = probe_feature(&["portable_simd"], "other");
+        assert_eq!(works, vec![false]);
+        assert_eq!(
+            pending[2],
+            "cargo:warning=the feature gate `portable_simd` could be enabled before, but now \
+             fails: error[E0554]: #![feature] may not be used on the stable release channel"
+        )
+    }
+
+    #[test]
+    fn test_cfg_alias() {
+        let var = |var: &str| match var {
+            "CARGO_CFG_TARGET_ARCH" => Some("x86_64".to_string()),
+            _ => None,
+        };
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .cfg_alias_in("x86_64", r#"target_arch = "x86_64""#, &var)
+            .cfg_alias_in(
+                "aarch64",
+                r#"any(target_arch = "aarch64", target_feature = "neon")"#,
+                &var,
+            );
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:rustc-check-cfg=cfg(x86_64)",
+            "cargo:rustc-cfg=x86_64",
+            "cargo:rustc-check-cfg=cfg(aarch64)",
+        ];
+        assert_eq!(pending, expected)
+    }
+
+    #[test]
+    fn test_link_pkg_config() {
+        use crate::pkg_config::Library;
+
+        let library = Library {
+            name: "libssl".into(),
+            version: "3.0.13".into(),
+            link_paths: vec!["/opt/ssl/lib".into()],
+            libs: vec![
+                "ssl".into(),
+                "framework=Security".into(),
+                ":libfoo.a".into(),
+                ":libbar.so.1".into(),
+            ],
+            link_args: vec!["-pthread".into()],
+            include_paths: vec!["/opt/ssl/include".into(), "/opt/zlib/include".into()],
+            files: vec!["/opt/ssl/lib/pkgconfig/libssl.pc".into()],
+        };
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.link_pkg_config(&library);
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:rerun-if-changed=/opt/ssl/lib/pkgconfig/libssl.pc",
+            "cargo:rustc-link-search=native=/opt/ssl/lib",
+            "cargo:rustc-link-lib=ssl",
+            "cargo:rustc-link-lib=framework=Security",
+            "cargo:rustc-link-lib=static:+verbatim=libfoo.a",
+            "cargo:rustc-link-lib=dylib:+verbatim=libbar.so.1",
+            "cargo:rustc-link-arg=-pthread",
+            "cargo:include=/opt/ssl/include:/opt/zlib/include",
+        ];
+        assert_eq!(pending, expected)
+    }
+
+    #[test]
+    fn test_find_library() {
+        use crate::library::{Preference, Search};
+        use crate::utils::TempDir;
+
+        let dir = TempDir::new("find_library");
+        let (emitted, user, standard) = (
+            dir.path().join("emitted"),
+            dir.path().join("user"),
+            dir.path().join("standard"),
+        );
+
+        for (dir, files) in [
+            (&emitted, &["libssl.so.3"][..]),
+            (&user, &["libssl.a", "libz.so"]),
+            (&standard, &["libm.a"]),
+        ] {
+            std::fs::create_dir_all(dir).unwrap();
+
+            for file in files {
+                std::fs::write(dir.join(file), "").unwrap();
+            }
+        }
+
+        let mut search = Search {
+            dirs: vec![user.clone()],
+            standard_dirs: vec![standard.clone()],
+            target_os: "linux".into(),
+            target_env: "gnu".into(),
+            preference: Preference::PreferDynamic,
+        };
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.cargo_rustc_link_search(None, emitted.clone());
+        let ssl = build_script.find_library_with("ssl", &search).unwrap();
+        assert_eq!(ssl, emitted.join("libssl.so.3"));
+        assert_eq!(
+            build_script.find_library_with("z", &search).unwrap(),
+            user.join("libz.so")
+        );
+        search.preference = Preference::StaticOnly;
+        let ssl = build_script.find_library_with("ssl", &search).unwrap();
+        assert_eq!(ssl, user.join("libssl.a"));
+        assert!(build_script.find_library_with("z", &search).is_err());
+        let m = build_script.find_library_with("m", &search).unwrap();
+        assert_eq!(m, standard.join("libm.a"));
+        let pending: Vec<_> = build_script
+            .instructions()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            format!("cargo:rustc-link-search={}", emitted.display()),
+            "cargo:rustc-link-lib=dylib:+verbatim=libssl.so.3".into(),
+            format!("cargo:rustc-link-search=native={}", user.display()),
+            "cargo:rustc-link-lib=dylib=z".into(),
+            "cargo:rustc-link-lib=static=ssl".into(),
+            "cargo:rustc-link-lib=static=m".into(),
+        ];
+        assert_eq!(pending, expected)
+    }
 }
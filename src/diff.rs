@@ -0,0 +1,387 @@
+//! Diffing two sets of [`Instruction`](Instruction)s, such as a build script's output before and
+//! after a change.
+//! # Notes
+//! The diff understands what the instructions mean. Instructions whose order matters, such as
+//! `rustc-link-lib` (see [`Instruction::is_order_sensitive()`](Instruction::is_order_sensitive)),
+//! are compared as sequences of the same name, so moving one is reported as reordered. Every
+//! other instruction, such as `rerun-if-changed` or `rustc-cfg`, is compared as a set, so its
+//! order and duplicates don't matter. `rustc-cfg`s with a single value are also paired by key, so
+//! a new value for such a key is reported as changed instead of removed and added. Keys which can
+//! have several values, such as `feature`, or which have several in either set, aren't paired, as
+//! their values are independent of each other.
+use crate::{Instruction, Prefix, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The difference between two sets of [`Instruction`](Instruction)s.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Diff {
+    /// The instructions only in the new set, in the order of the new set.
+    pub added: Vec<Instruction>,
+
+    /// The instructions only in the old set, in the order of the old set.
+    pub removed: Vec<Instruction>,
+
+    /// The order sensitive instructions in both sets whose position relative to the other
+    /// instructions of the same name changed, in the order of the new set.
+    pub reordered: Vec<Instruction>,
+
+    /// The single valued `rustc-cfg`s whose key is in both sets but with a different value, as the
+    /// old and the new instruction, in the order of the new set.
+    pub changed: Vec<(Instruction, Instruction)>,
+}
+
+impl Diff {
+    /// Returns `true` if both sets are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.reordered.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for Diff {
+    /// A human readable report. Each line is an instruction, starting with `-` if it was removed,
+    /// `+` if it was added, `~` if it was reordered or `*` if its value changed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+
+        for instruction in &self.removed {
+            writeln!(f, "- {}", instruction)?
+        }
+
+        for instruction in &self.added {
+            writeln!(f, "+ {}", instruction)?
+        }
+
+        for instruction in &self.reordered {
+            writeln!(f, "~ {} (reordered)", instruction)?
+        }
+
+        for (old, new) in &self.changed {
+            writeln!(f, "* {} -> {}", old, new)?
+        }
+
+        Ok(())
+    }
+}
+
+/// Diff the `old` and `new` sets of instructions.
+pub fn diff(old: &[Instruction], new: &[Instruction]) -> Diff {
+    let mut removed = vec![false; old.len()];
+    let mut added = vec![false; new.len()];
+    let mut reordered = vec![false; new.len()];
+
+    // order sensitive instructions, compared as sequences grouped by name.
+    let mut sequences: BTreeMap<_, (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+
+    for (index, instruction) in old.iter().enumerate() {
+        if instruction.is_order_sensitive() {
+            sequences
+                .entry(&instruction.name)
+                .or_default()
+                .0
+                .push(index)
+        }
+    }
+
+    for (index, instruction) in new.iter().enumerate() {
+        if instruction.is_order_sensitive() {
+            sequences
+                .entry(&instruction.name)
+                .or_default()
+                .1
+                .push(index)
+        }
+    }
+
+    for (old_indices, new_indices) in sequences.values() {
+        let (old_common, new_common) =
+            match_common(old_indices, new_indices, |old_index, new_index| {
+                old[old_index] == new[new_index]
+            });
+
+        for &index in old_indices {
+            removed[index] = !old_common.contains(&index)
+        }
+
+        for &index in new_indices {
+            added[index] = !new_common.contains(&index)
+        }
+
+        let in_order =
+            longest_common_subsequence(&old_common, &new_common, |old_index, new_index| {
+                old[old_index] == new[new_index]
+            });
+
+        for index in new_common {
+            reordered[index] = !in_order.contains(&index)
+        }
+    }
+
+    // every other instruction, compared as a set.
+    let old_set: BTreeSet<_> = old.iter().filter(|i| !i.is_order_sensitive()).collect();
+    let new_set: BTreeSet<_> = new.iter().filter(|i| !i.is_order_sensitive()).collect();
+
+    collect_unique(old, &mut removed, |instruction| {
+        !instruction.is_order_sensitive() && !new_set.contains(instruction)
+    });
+    collect_unique(new, &mut added, |instruction| {
+        !instruction.is_order_sensitive() && !old_set.contains(instruction)
+    });
+
+    // single valued cfgs only in one set, paired by key.
+    let single_valued = |key: &str| {
+        let values = |instructions: &[Instruction]| {
+            instructions
+                .iter()
+                .filter(|instruction| cfg_key(instruction) == Some(key))
+                .collect::<BTreeSet<_>>()
+                .len()
+        };
+
+        !MULTI_VALUED_CFGS.contains(&key) && values(old) <= 1 && values(new) <= 1
+    };
+    let mut unpaired: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for (index, instruction) in old.iter().enumerate() {
+        if let (true, Some(key)) = (removed[index], cfg_key(instruction)) {
+            if single_valued(key) {
+                unpaired.insert(key, index);
+            }
+        }
+    }
+
+    let mut changed = Vec::new();
+
+    for (index, instruction) in new.iter().enumerate() {
+        if let (true, Some(key)) = (added[index], cfg_key(instruction)) {
+            if let Some(old_index) = unpaired.remove(key) {
+                removed[old_index] = false;
+                added[index] = false;
+                changed.push((old[old_index].clone(), instruction.clone()))
+            }
+        }
+    }
+
+    let pick = |instructions: &[Instruction], flags: &[bool]| {
+        instructions
+            .iter()
+            .zip(flags)
+            .filter(|(_, &flag)| flag)
+            .map(|(instruction, _)| instruction.clone())
+            .collect()
+    };
+
+    Diff {
+        added: pick(new, &added),
+        removed: pick(old, &removed),
+        reordered: pick(new, &reordered),
+        changed,
+    }
+}
+
+/// The `cfg` keys which commonly have several values at once.
+const MULTI_VALUED_CFGS: &[&str] = &["feature", "target_feature"];
+
+/// The key of a `rustc-cfg`, such as `ossl300` or `osslconf`.
+fn cfg_key(instruction: &Instruction) -> Option<&str> {
+    match (
+        &instruction.prefix,
+        instruction.name.as_deref(),
+        &instruction.value,
+    ) {
+        (Prefix::Cargo, Some("rustc-cfg"), Value::OptionalValue(key, _)) => Some(key),
+        _ => None,
+    }
+}
+
+/// Flag the first occurrence of every instruction matching `predicate`.
+fn collect_unique(
+    instructions: &[Instruction],
+    flags: &mut [bool],
+    predicate: impl Fn(&Instruction) -> bool,
+) {
+    let mut seen = BTreeSet::new();
+
+    for (instruction, flag) in instructions.iter().zip(flags) {
+        if predicate(instruction) && seen.insert(instruction) {
+            *flag = true
+        }
+    }
+}
+
+/// Match the elements present in both sequences, counting duplicates. Returns the indices of the
+/// matched elements of each sequence, in order.
+fn match_common(
+    old: &[usize],
+    new: &[usize],
+    eq: impl Fn(usize, usize) -> bool,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut unmatched = old.to_vec();
+    let mut old_common = Vec::new();
+    let mut new_common = Vec::new();
+
+    for &new_index in new {
+        if let Some(position) = unmatched
+            .iter()
+            .position(|&old_index| eq(old_index, new_index))
+        {
+            old_common.push(unmatched.remove(position));
+            new_common.push(new_index)
+        }
+    }
+
+    old_common.sort_unstable();
+
+    (old_common, new_common)
+}
+
+/// Find the longest common subsequence of two sequences. Returns the indices of the elements of
+/// `new` which are part of it.
+fn longest_common_subsequence(
+    old: &[usize],
+    new: &[usize],
+    eq: impl Fn(usize, usize) -> bool,
+) -> Vec<usize> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if eq(old[i], new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            }
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut subsequence = Vec::new();
+
+    while i < old.len() && j < new.len() {
+        if eq(old[i], new[j]) {
+            subsequence.push(new[j]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1
+        } else {
+            j += 1
+        }
+    }
+
+    subsequence
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Instruction, Value};
+
+    fn link_lib(name: &str) -> Instruction {
+        Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(None, name.into()),
+        )
+    }
+
+    fn rerun(path: &str) -> Instruction {
+        Instruction::new("rerun-if-changed", Value::Singular(path.into()))
+    }
+
+    fn cfg(key: &str, value: Option<&str>) -> Instruction {
+        Instruction::new(
+            "rustc-cfg",
+            Value::OptionalValue(key.into(), value.map(Into::into)),
+        )
+    }
+
+    #[test]
+    fn test_empty() {
+        let instructions = vec![link_lib("ssl"), rerun("build.rs")];
+        let diff = super::diff(&instructions, &instructions);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes\n")
+    }
+
+    #[test]
+    fn test_rerun_unordered() {
+        let old = vec![rerun("a"), rerun("b"), rerun("a")];
+        let new = vec![rerun("b"), rerun("a")];
+        assert!(super::diff(&old, &new).is_empty())
+    }
+
+    #[test]
+    fn test_cfg_by_key() {
+        let old = vec![
+            cfg("ossl300", None),
+            cfg("osslconf", Some("A")),
+            cfg("feature", Some("x")),
+        ];
+        let new = vec![
+            cfg("osslconf", Some("B")),
+            cfg("ossl300", None),
+            cfg("feature", Some("y")),
+        ];
+        let diff = super::diff(&old, &new);
+        let expected = vec![(cfg("osslconf", Some("A")), cfg("osslconf", Some("B")))];
+        assert_eq!(diff.changed, expected);
+        assert_eq!(diff.removed, vec![cfg("feature", Some("x"))]);
+        assert_eq!(diff.added, vec![cfg("feature", Some("y"))]);
+        assert!(diff.reordered.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "- cargo:rustc-cfg=feature=\"x\"\n\
+             + cargo:rustc-cfg=feature=\"y\"\n\
+             * cargo:rustc-cfg=osslconf=\"A\" -> cargo:rustc-cfg=osslconf=\"B\"\n"
+        )
+    }
+
+    #[test]
+    fn test_cfg_multi_valued() {
+        let old = vec![cfg("backend", Some("a")), cfg("backend", Some("b"))];
+        let new = vec![cfg("backend", Some("a")), cfg("backend", Some("c"))];
+        let diff = super::diff(&old, &new);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.removed, vec![cfg("backend", Some("b"))]);
+        assert_eq!(diff.added, vec![cfg("backend", Some("c"))])
+    }
+
+    #[test]
+    fn test_link_order() {
+        let old = vec![link_lib("ssl"), link_lib("crypto"), link_lib("z")];
+        let new = vec![
+            link_lib("crypto"),
+            link_lib("ssl"),
+            link_lib("z"),
+            link_lib("dl"),
+        ];
+        let diff = super::diff(&old, &new);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.added, vec![link_lib("dl")]);
+        assert_eq!(diff.reordered, vec![link_lib("ssl")])
+    }
+
+    #[test]
+    fn test_link_duplicates() {
+        let old = vec![link_lib("a"), link_lib("b"), link_lib("a")];
+        let new = vec![link_lib("a"), link_lib("b")];
+        let diff = super::diff(&old, &new);
+        assert_eq!(diff.removed, vec![link_lib("a")]);
+        assert!(diff.added.is_empty() && diff.reordered.is_empty())
+    }
+
+    #[test]
+    fn test_display() {
+        let old = vec![link_lib("ssl"), link_lib("crypto"), rerun("old.h")];
+        let new = vec![link_lib("crypto"), link_lib("ssl"), rerun("new.h")];
+        let report = super::diff(&old, &new).to_string();
+        let expected = "- cargo:rerun-if-changed=old.h\n\
+                        + cargo:rerun-if-changed=new.h\n\
+                        ~ cargo:rustc-link-lib=ssl (reordered)\n";
+        assert_eq!(report, expected)
+    }
+}
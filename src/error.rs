@@ -0,0 +1,61 @@
+//! This contains the [`Error`](Error) enum.
+use crate::conflict::Conflict;
+use crate::intercept::Rejection;
+use std::fmt;
+#[cfg(feature = "manifest")]
+use std::io;
+
+/// An error which occurred while building. See
+/// [`BuildScript::try_build()`](crate::BuildScript::try_build).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Instructions in the stack conflict with each other, and the
+    /// [`Resolution`](crate::conflict::Resolution) is [`Error`](crate::conflict::Resolution::Error).
+    Conflicts(Vec<Conflict>),
+
+    /// Instructions were rejected by an [`Interceptor`](crate::intercept::Interceptor).
+    Rejected(Vec<Rejection>),
+
+    /// The manifest couldn't be written. See
+    /// [`BuildScript::manifest()`](crate::BuildScript::manifest).
+    #[cfg(feature = "manifest")]
+    Manifest(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Conflicts(conflicts) => {
+                write!(f, "{} conflict(s) between instructions", conflicts.len())?;
+
+                for conflict in conflicts {
+                    write!(f, "\n{}", conflict)?
+                }
+
+                Ok(())
+            }
+            Self::Rejected(rejections) => {
+                write!(f, "{} instruction(s) rejected", rejections.len())?;
+
+                for rejection in rejections {
+                    write!(f, "\n{}", rejection)?
+                }
+
+                Ok(())
+            }
+            #[cfg(feature = "manifest")]
+            Self::Manifest(error) => write!(f, "failed to write the manifest: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "manifest")]
+            Self::Manifest(error) => Some(error),
+            _ => None,
+        }
+    }
+}
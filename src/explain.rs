@@ -0,0 +1,237 @@
+//! Plain-English explanations of what cargo does with each instruction. See
+//! [`BuildScript::explain()`](crate::BuildScript::explain).
+use crate::{Instruction, Prefix, Value};
+use std::fmt;
+
+/// The environment variable which enables explaining by default when set to `1`, `true`, `yes` or
+/// `on`.
+pub const ENV: &str = "BUILD_SCRIPT_EXPLAIN";
+
+/// What cargo does with an instruction, and which targets it affects.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Explanation {
+    /// What cargo does with the instruction.
+    pub effect: String,
+
+    /// Which targets the instruction affects.
+    pub affects: String,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Affects {}.", self.effect, self.affects)
+    }
+}
+
+/// Returns `true` if [`ENV`](ENV) enables explaining.
+pub(crate) fn enabled_by_env() -> bool {
+    matches!(
+        std::env::var(ENV).as_deref(),
+        Ok("1") | Ok("true") | Ok("yes") | Ok("on")
+    )
+}
+
+/// Explain an instruction.
+pub fn explain(instruction: &Instruction) -> Explanation {
+    /// Every target of the package.
+    const ALL: &str = "every target of this package";
+
+    /// The targets which are linked.
+    const LINKED: &str = "the library of this package and the binaries, tests, examples and \
+                          benchmarks linking it";
+
+    let explanation = |effect: String, affects: &str| Explanation {
+        effect,
+        affects: affects.into(),
+    };
+    let name = match (&instruction.prefix, &instruction.name) {
+        (Prefix::Cargo, Some(name)) => name.as_str(),
+        (Prefix::Cargo, None) => {
+            let effect = match &instruction.value {
+                Value::UnquotedMapping(key, value) | Value::Mapping(key, value) => format!(
+                    "Cargo passes the metadata `{}` = `{}` to the build scripts of packages \
+                     depending on this one as `DEP_<LINKS>_{}`, if this package sets `links`.",
+                    key,
+                    value,
+                    key.to_uppercase().replace('-', "_")
+                ),
+                value => format!(
+                    "Cargo passes the metadata `{}` to dependent packages.",
+                    value
+                ),
+            };
+
+            return explanation(effect, "the build scripts of dependent packages");
+        }
+        (Prefix::Custom(prefix), _) => {
+            let effect = format!(
+                "Cargo ignores this, since it uses the `{}` prefix instead of `cargo`.",
+                prefix
+            );
+
+            return explanation(effect, "nothing");
+        }
+    };
+    let value = &instruction.value;
+
+    match (name, value) {
+        ("rerun-if-changed", value) => explanation(
+            format!("Cargo reruns the build script when `{}` changes.", value),
+            "the build script",
+        ),
+        ("rerun-if-env-changed", value) => explanation(
+            format!(
+                "Cargo reruns the build script when the environment variable `{}` changes.",
+                value
+            ),
+            "the build script",
+        ),
+        ("rustc-link-lib", Value::UnquotedOptionalKey(kind, library)) => {
+            let kind = match kind {
+                Some(kind) => format!(" as `{}`", kind),
+                None => String::new(),
+            };
+
+            explanation(
+                format!(
+                    "Cargo passes `-l {}` to rustc, linking the native library `{}`{}.",
+                    value, library, kind
+                ),
+                LINKED,
+            )
+        }
+        ("rustc-link-search", value) => explanation(
+            format!(
+                "Cargo passes `-L {}` to rustc, adding it to the library search path.",
+                value
+            ),
+            LINKED,
+        ),
+        ("rustc-flags", value) => explanation(
+            format!(
+                "Cargo passes `{}` to rustc. Only `-l` and `-L` are allowed.",
+                value
+            ),
+            LINKED,
+        ),
+        ("rustc-cfg", value) => explanation(
+            format!(
+                "Cargo passes `--cfg {}` to rustc, enabling code behind `#[cfg({})]`.",
+                value, value
+            ),
+            ALL,
+        ),
+        ("rustc-check-cfg", value) => explanation(
+            format!(
+                "Cargo passes `--check-cfg {}` to rustc, so `unexpected_cfgs` doesn't warn about \
+                 it.",
+                value
+            ),
+            ALL,
+        ),
+        ("rustc-env", Value::UnquotedMapping(var, value)) => explanation(
+            format!(
+                "Cargo sets the environment variable `{}` to `{}` while compiling, readable \
+                 through `env!(\"{}\")`.",
+                var, value, var
+            ),
+            ALL,
+        ),
+        ("rustc-link-arg", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the binaries, tests, examples, benchmarks and `cdylib` of this package",
+        ),
+        ("rustc-link-arg-bin", Value::UnquotedMapping(bin, value)) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            &format!("the binary `{}`", bin),
+        ),
+        ("rustc-link-arg-bins", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the binaries of this package",
+        ),
+        ("rustc-link-arg-tests", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the tests of this package",
+        ),
+        ("rustc-link-arg-examples", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the examples of this package",
+        ),
+        ("rustc-link-arg-benches", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the benchmarks of this package",
+        ),
+        ("rustc-link-arg-cdylib", value) | ("rustc-cdylib-link-arg", value) => explanation(
+            format!("Cargo passes `-C link-arg={}` to the linker.", value),
+            "the `cdylib` of this package",
+        ),
+        ("warning", value) => explanation(
+            format!(
+                "Cargo shows the warning `{}` after the build script runs.",
+                value
+            ),
+            "nothing",
+        ),
+        ("error", value) => explanation(
+            format!("Cargo fails the build with the error `{}`.", value),
+            "the whole build",
+        ),
+        ("metadata", value) => explanation(
+            format!(
+                "Cargo passes the metadata `{}` to the build scripts of packages depending on \
+                 this one, if this package sets `links`.",
+                value
+            ),
+            "the build scripts of dependent packages",
+        ),
+        (name, value) => explanation(
+            format!(
+                "Cargo doesn't know `{}`, so it is treated as the metadata `{}` = `{}`.",
+                name, name, value
+            ),
+            "the build scripts of dependent packages",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+    use crate::{Instruction, Prefix, Value};
+
+    #[test]
+    fn test_explain() {
+        let link_lib = Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(Some("static".into()), "z".into()),
+        );
+        let explanation = explain(&link_lib);
+        assert_eq!(
+            explanation.effect,
+            "Cargo passes `-l static=z` to rustc, linking the native library `z` as `static`."
+        );
+        assert!(explanation
+            .affects
+            .starts_with("the library of this package"));
+
+        let link_arg = Instruction::new(
+            "rustc-link-arg-bin",
+            Value::UnquotedMapping("cli".into(), "-Wl,--as-needed".into()),
+        );
+        assert_eq!(explain(&link_arg).affects, "the binary `cli`");
+
+        let env = Instruction::new(
+            "rustc-env",
+            Value::UnquotedMapping("VERSION".into(), "1.0".into()),
+        );
+        assert_eq!(
+            explain(&env).to_string(),
+            "Cargo sets the environment variable `VERSION` to `1.0` while compiling, readable \
+             through `env!(\"VERSION\")`. Affects every target of this package."
+        );
+
+        let mut custom = Instruction::new("thing", Value::Singular("value".into()));
+        custom.prefix = Prefix::Custom("other".into());
+        assert_eq!(explain(&custom).affects, "nothing")
+    }
+}
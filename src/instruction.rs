@@ -5,7 +5,15 @@ use std::fmt;
 use std::fmt::Formatter;
 
 /// An instruction. Used as a rusty way to parse arguments in build scripts.
+/// # Notes
+/// With the `serde` feature, deserializing an instruction without a name fails unless its value
+/// is a mapping, just like [`new_mapping()`](Self::new_mapping).
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RawInstruction")
+)]
 pub struct Instruction {
     /// The prefix. Usually [`Cargo`](Prefix::Cargo).
     pub prefix: Prefix,
@@ -59,6 +67,58 @@ impl Instruction {
 
         self
     }
+
+    /// Returns `true` if the order of this instruction relative to others of the same name
+    /// matters to the linker, such as with `rustc-link-lib` and `rustc-link-search`.
+    pub fn is_order_sensitive(&self) -> bool {
+        /// The names of the instructions whose order matters.
+        const ORDER_SENSITIVE: &[&str] = &[
+            "rustc-link-lib",
+            "rustc-link-search",
+            "rustc-flags",
+            "rustc-link-arg",
+            "rustc-link-arg-bin",
+            "rustc-link-arg-bins",
+            "rustc-link-arg-tests",
+            "rustc-link-arg-examples",
+            "rustc-link-arg-benches",
+            "rustc-link-arg-cdylib",
+            "rustc-cdylib-link-arg",
+        ];
+
+        match (&self.prefix, &self.name) {
+            (Prefix::Cargo, Some(name)) => ORDER_SENSITIVE.contains(&name.as_str()),
+            _ => false,
+        }
+    }
+}
+
+/// An [`Instruction`](Instruction) which hasn't been validated yet.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawInstruction {
+    #[serde(default)]
+    prefix: Prefix,
+    #[serde(default)]
+    name: Option<String>,
+    value: Value,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RawInstruction> for Instruction {
+    type Error = &'static str;
+
+    fn try_from(raw: RawInstruction) -> Result<Self, Self::Error> {
+        if raw.name.is_none() && !(raw.value.is_mapping() || raw.value.is_unquoted_mapping()) {
+            return Err("value type must be [Unquoted]Mapping");
+        }
+
+        Ok(Self {
+            prefix: raw.prefix,
+            name: raw.name,
+            value: raw.value,
+        })
+    }
 }
 
 impl fmt::Display for Instruction {
@@ -114,6 +174,32 @@ mod tests {
         assert!(instruction.name.is_none());
     }
 
+    #[test]
+    fn test_is_order_sensitive() {
+        let value = Value::Singular("ssl".into());
+        assert!(Instruction::new("rustc-link-lib", value.clone()).is_order_sensitive());
+        assert!(!Instruction::new("rustc-cfg", value.clone()).is_order_sensitive());
+        let mut instruction = Instruction::new("rustc-link-lib", value);
+        instruction.prefix(Prefix::Custom("custom".into()));
+        assert!(!instruction.is_order_sensitive())
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let instruction = Instruction::new("rerun-if-changed", Value::Singular("build.rs".into()));
+        let json = serde_json::to_string(&instruction).unwrap();
+        let expected =
+            r#"{"prefix":"cargo","name":"rerun-if-changed","value":{"singular":"build.rs"}}"#;
+        assert_eq!(json, expected);
+        let deserialized: Instruction = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, instruction);
+        let mapping: Instruction =
+            serde_json::from_str(r#"{"value":{"unquoted_mapping":["key","value"]}}"#).unwrap();
+        assert_eq!(mapping.to_string(), "cargo:key=value");
+        assert!(serde_json::from_str::<Instruction>(r#"{"value":{"singular":"value"}}"#).is_err())
+    }
+
     new_wrong_value_test!(
         test_new_mapping_fails_if_value_singular,
         Value::Singular("".into())
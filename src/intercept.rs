@@ -0,0 +1,150 @@
+//! Hooks which see every instruction before it is queued or written. See
+//! [`BuildScript::intercept()`](crate::BuildScript::intercept).
+//! # Examples
+//! ```rust
+//! use build_script::intercept::Action;
+//! use build_script::{BuildScript, Value};
+//!
+//! let mut build_script = BuildScript::default();
+//! build_script.intercept(|instruction: build_script::Instruction| {
+//!     match (instruction.name.as_deref(), &instruction.value) {
+//!         (Some("rustc-link-search"), Value::UnquotedOptionalKey(_, path))
+//!             if path.starts_with("/usr/local") =>
+//!         {
+//!             Action::Drop
+//!         }
+//!         (Some("rustc-flags"), _) => Action::Reject("rustc-flags is forbidden".into()),
+//!         _ => Action::Pass(instruction),
+//!     }
+//! });
+//! build_script.cargo_rustc_link_search(None, "/usr/local/lib".into());
+//! assert!(build_script.is_empty());
+//! ```
+use crate::conflict::Origin;
+use crate::Instruction;
+use std::fmt;
+use std::panic::Location;
+
+/// What to do with an intercepted instruction.
+#[derive(Debug)]
+pub enum Action {
+    /// Pass the instruction on, which may have been modified.
+    Pass(Instruction),
+
+    /// Drop the instruction.
+    Drop,
+
+    /// Replace the instruction with several, possibly none.
+    Expand(Vec<Instruction>),
+
+    /// Drop the instruction, and fail [`try_build()`](crate::BuildScript::try_build) with the
+    /// error.
+    Reject(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Something which sees every instruction before it is queued or written.
+/// # Notes
+/// This is implemented for every `FnMut(Instruction) -> Action`.
+pub trait Interceptor {
+    /// Decide what to do with an instruction.
+    fn intercept(&mut self, instruction: Instruction) -> Action;
+}
+
+impl<F: FnMut(Instruction) -> Action> Interceptor for F {
+    fn intercept(&mut self, instruction: Instruction) -> Action {
+        self(instruction)
+    }
+}
+
+/// An instruction which was rejected by an [`Interceptor`](Interceptor).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rejection {
+    /// The rejected instruction, as the interceptor which rejected it saw it.
+    pub origin: Origin,
+
+    /// Why it was rejected.
+    pub reason: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} was rejected: {}", self.origin, self.reason)
+    }
+}
+
+/// Pass an instruction through every interceptor in order. Returns the resulting instructions, or
+/// the first rejection of one of them.
+pub(crate) fn apply(
+    interceptors: &mut [Box<dyn Interceptor + Send>],
+    instruction: Instruction,
+    location: &'static Location<'static>,
+) -> Result<Vec<Instruction>, Box<Rejection>> {
+    let mut instructions = vec![instruction];
+
+    for interceptor in interceptors {
+        let mut next = Vec::with_capacity(instructions.len());
+
+        for instruction in instructions {
+            match interceptor.intercept(instruction.clone()) {
+                Action::Pass(instruction) => next.push(instruction),
+                Action::Drop => {}
+                Action::Expand(expanded) => next.extend(expanded),
+                Action::Reject(error) => {
+                    return Err(Box::new(Rejection {
+                        origin: Origin {
+                            instruction,
+                            location,
+                        },
+                        reason: error.to_string(),
+                    }))
+                }
+            }
+        }
+
+        instructions = next
+    }
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Interceptor};
+    use crate::{Instruction, Value};
+    use std::panic::Location;
+
+    fn link_lib(name: &str) -> Instruction {
+        Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(None, name.into()),
+        )
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut interceptors: Vec<Box<dyn Interceptor + Send>> = vec![
+            Box::new(|instruction: Instruction| match &instruction.value {
+                Value::UnquotedOptionalKey(_, name) if name == "ssl" => {
+                    Action::Expand(vec![link_lib("ssl"), link_lib("crypto")])
+                }
+                _ => Action::Pass(instruction),
+            }),
+            Box::new(|instruction: Instruction| match &instruction.value {
+                Value::UnquotedOptionalKey(_, name) if name == "crypto" => Action::Drop,
+                Value::UnquotedOptionalKey(_, name) if name == "forbidden" => {
+                    Action::Reject("forbidden library".into())
+                }
+                _ => Action::Pass(instruction),
+            }),
+        ];
+        let location = Location::caller();
+        assert_eq!(
+            super::apply(&mut interceptors, link_lib("ssl"), location),
+            Ok(vec![link_lib("ssl")])
+        );
+        let rejection =
+            super::apply(&mut interceptors, link_lib("forbidden"), location).unwrap_err();
+        assert_eq!(rejection.origin.instruction, link_lib("forbidden"));
+        assert_eq!(rejection.reason, "forbidden library")
+    }
+}
@@ -30,19 +30,49 @@
 //! // write all instructions to something (for this scenario, and also usually, its stdout)
 //! build_script.build();
 //! ```
+//! # Features
+//! - `serde`: `Serialize` and `Deserialize` for [`Instruction`](Instruction), [`Value`](Value),
+//!   [`Prefix`](Prefix) and the link `Kind`s, and the `JsonLines` output format.
+//! - `manifest`: write a machine readable manifest of everything a build script did to
+//!   `OUT_DIR` (implies `serde`).
+//! - `message`: parse the `build-script-executed` messages of `cargo --message-format=json`
+//!   into this crate's types.
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
 pub mod basic;
 pub mod cargo_rustc_link_lib;
 pub mod cargo_rustc_link_search;
+pub mod cfg;
+pub mod conflict;
 pub mod core;
+pub mod diff;
+pub mod error;
+pub mod explain;
 pub mod instruction;
+pub mod intercept;
+pub mod library;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+#[cfg(feature = "message")]
+pub mod message;
+pub mod output;
+pub mod pkg_config;
+pub mod policy;
 pub mod prefix;
+pub mod probe;
+mod run;
+pub mod rustc;
+pub mod rustc_args;
+pub mod rustc_flags;
+pub mod shared;
+pub mod step;
 mod utils;
 pub mod value;
 
 pub use self::core::BuildScript;
 pub use basic::*;
+pub use error::Error;
 pub use instruction::Instruction;
 pub use prefix::Prefix;
+pub use run::run;
 pub use value::Value;
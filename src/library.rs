@@ -0,0 +1,468 @@
+//! Locating native libraries on disk. See
+//! [`BuildScript::find_library()`](crate::BuildScript::find_library).
+//! # Examples
+//! ```rust,no_run
+//! use build_script::library::{Preference, Search};
+//! use build_script::BuildScript;
+//!
+//! let mut build_script = BuildScript::default();
+//! let mut search = Search::from_env();
+//! search.dirs.push("/opt/ssl/lib".into());
+//! search.preference = Preference::StaticOnly;
+//! let path = build_script.find_library_with("ssl", &search).unwrap();
+//! println!("linking {}", path.display());
+//! build_script.build();
+//! ```
+use crate::cargo_rustc_link_lib::Kind;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
+
+/// Which kind of library to pick when both are found. Like the linker, the first directory holding
+/// the library wins, so the preference only picks between the files of that directory.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Preference {
+    /// Pick a static library if the directory has one, else a dynamic one.
+    PreferStatic,
+
+    /// Pick a dynamic library if the directory has one, else a static one. This is the default.
+    PreferDynamic,
+
+    /// Only pick a static library, skipping directories without one.
+    StaticOnly,
+}
+
+impl Default for Preference {
+    /// The default is [`PreferDynamic`](Self::PreferDynamic).
+    fn default() -> Self {
+        Self::PreferDynamic
+    }
+}
+
+/// Where and how to look for a library.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Search {
+    /// Directories searched after the link paths already passed, before `standard_dirs`.
+    pub dirs: Vec<PathBuf>,
+
+    /// The standard library directories of the target, searched last.
+    pub standard_dirs: Vec<PathBuf>,
+
+    /// The operating system of the target, such as `linux`, which decides the file names.
+    pub target_os: String,
+
+    /// The environment of the target, such as `gnu` or `msvc`, which decides the file names on
+    /// Windows.
+    pub target_env: String,
+
+    /// Which kind of library to pick.
+    pub preference: Preference,
+}
+
+impl Search {
+    /// Search the standard library directories of the target, from `TARGET`,
+    /// `CARGO_CFG_TARGET_OS`, `CARGO_CFG_TARGET_ENV` and `CARGO_CFG_TARGET_POINTER_WIDTH`, or of
+    /// the host if they aren't set. On macOS, Homebrew's `/opt/homebrew/lib` is added to `dirs`,
+    /// as the linker doesn't search it.
+    /// # Notes
+    /// When cross-compiling (`HOST` and `TARGET` differ), the standard directories would hold
+    /// libraries of the host, so none are searched, except those in `LIB` on Windows. The
+    /// directories of the target must then be added to `dirs`.
+    pub fn from_env() -> Self {
+        Self::from_env_in(&|var| env::var(var).ok())
+    }
+
+    /// [`from_env()`](Self::from_env), reading variables through `var`.
+    fn from_env_in(var: &dyn Fn(&str) -> Option<String>) -> Self {
+        let target_os = var("CARGO_CFG_TARGET_OS").unwrap_or_else(|| env::consts::OS.into());
+        let target_env = var("CARGO_CFG_TARGET_ENV").unwrap_or_else(|| {
+            match (target_os.as_str(), cfg!(target_env = "gnu")) {
+                ("windows", true) => "gnu".into(),
+                ("windows", false) => "msvc".into(),
+                _ => String::new(),
+            }
+        });
+        let pointer_width =
+            var("CARGO_CFG_TARGET_POINTER_WIDTH").unwrap_or_else(|| (usize::BITS).to_string());
+        let target = var("TARGET");
+        let cross = match (var("HOST"), &target) {
+            (Some(host), Some(target)) => host != *target,
+            _ => false,
+        };
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        let mut standard_dirs: Vec<PathBuf> = Vec::new();
+
+        match target_os.as_str() {
+            "windows" => {
+                if let Some(lib) = var("LIB") {
+                    standard_dirs.extend(env::split_paths(&lib))
+                }
+            }
+            _ if cross => {}
+            "macos" | "ios" => {
+                dirs.push("/opt/homebrew/lib".into());
+                standard_dirs.extend(["/usr/lib", "/usr/local/lib"].iter().map(PathBuf::from))
+            }
+            _ => {
+                standard_dirs.push("/usr/local/lib".into());
+
+                if let Some(target) = &target {
+                    let multiarch = multiarch(target);
+                    standard_dirs.push(format!("/usr/lib/{}", multiarch).into());
+                    standard_dirs.push(format!("/lib/{}", multiarch).into());
+                }
+
+                if pointer_width == "64" {
+                    standard_dirs.extend(["/usr/lib64", "/lib64"].iter().map(PathBuf::from));
+                }
+
+                standard_dirs.extend(["/usr/lib", "/lib"].iter().map(PathBuf::from))
+            }
+        }
+
+        Self {
+            dirs,
+            standard_dirs,
+            target_os,
+            target_env,
+            preference: Preference::default(),
+        }
+    }
+
+    /// Find a library in `dirs`, in order, then in `self.dirs` and `self.standard_dirs`.
+    pub(crate) fn find(&self, name: &str, dirs: &[PathBuf]) -> Result<Found, Error> {
+        let dirs: Vec<_> = dirs
+            .iter()
+            .chain(&self.dirs)
+            .chain(&self.standard_dirs)
+            .collect();
+        let found = dirs.iter().find_map(|dir| {
+            let find_static = || self.find_static(dir, name);
+            let find_dynamic = || self.find_dynamic(dir, name);
+
+            match self.preference {
+                Preference::PreferStatic => find_static().or_else(find_dynamic),
+                Preference::PreferDynamic => find_dynamic().or_else(find_static),
+                Preference::StaticOnly => find_static(),
+            }
+        });
+
+        found.ok_or_else(|| Error::NotFound {
+            name: name.into(),
+            searched: dirs.into_iter().cloned().collect(),
+        })
+    }
+
+    /// Whether or not the target is Windows with the MSVC toolchain, where a `.lib` file can be
+    /// either a static or an import library.
+    fn is_msvc(&self) -> bool {
+        self.target_os == "windows" && self.target_env != "gnu"
+    }
+
+    /// Find a static library in a directory. With MSVC, a `.lib` file is found for any
+    /// preference, and linked without a kind, as telling a static library from an import library
+    /// would require reading it.
+    fn find_static(&self, dir: &Path, name: &str) -> Option<Found> {
+        let (file_name, kind) = if self.is_msvc() {
+            (format!("{}.lib", name), None)
+        } else {
+            (format!("lib{}.a", name), Some(Kind::Static))
+        };
+        let path = dir.join(file_name);
+
+        if path.is_file() {
+            Some(Found {
+                path,
+                kind,
+                verbatim: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Find a dynamic library in a directory. An unversioned file is preferred, else the highest
+    /// version is picked, which can only be linked by its full file name. On Windows, this is the
+    /// import library, `libfoo.dll.a` with GNU, while with MSVC it is found as a static library.
+    fn find_dynamic(&self, dir: &Path, name: &str) -> Option<Found> {
+        let (unversioned, versioned_prefix, versioned_suffix) = match self.target_os.as_str() {
+            "macos" | "ios" => (
+                format!("lib{}.dylib", name),
+                format!("lib{}.", name),
+                ".dylib",
+            ),
+            "windows" if self.is_msvc() => return None,
+            "windows" => (format!("lib{}.dll.a", name), String::new(), ""),
+            _ => (format!("lib{}.so", name), format!("lib{}.so.", name), ""),
+        };
+        let path = dir.join(&unversioned);
+
+        if path.is_file() {
+            return Some(Found {
+                path,
+                kind: Some(Kind::DynamicLibrary),
+                verbatim: false,
+            });
+        }
+
+        if versioned_prefix.is_empty() {
+            return None;
+        }
+
+        let mut versioned: Vec<_> = fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let file_name = path.file_name().and_then(|name| name.to_str());
+
+                match file_name.and_then(|name| name.strip_prefix(&versioned_prefix)) {
+                    Some(version) => {
+                        let version = version.strip_suffix(versioned_suffix).unwrap_or_default();
+
+                        !version.is_empty()
+                            && version.split('.').all(|part| part.parse::<u32>().is_ok())
+                            && path.is_file()
+                    }
+                    None => false,
+                }
+            })
+            .collect();
+        versioned.sort_by_key(|path| version_key(path, &versioned_prefix));
+
+        versioned.pop().map(|path| Found {
+            path,
+            kind: Some(Kind::DynamicLibrary),
+            verbatim: true,
+        })
+    }
+}
+
+/// The debian style multiarch name of a target, such as `x86_64-linux-gnu` for
+/// `x86_64-unknown-linux-gnu` or `i386-linux-gnu` for `i686-unknown-linux-gnu`, as in
+/// `/usr/lib/x86_64-linux-gnu`.
+pub(crate) fn multiarch(target: &str) -> String {
+    let parts: Vec<_> = target.split('-').collect();
+    // the vendor, such as `unknown`, isn't part of it.
+    let (arch, rest) = match parts.as_slice() {
+        [arch, _, os, env] => (*arch, format!("{}-{}", os, env)),
+        [arch, rest @ ..] => (*arch, rest.join("-")),
+        [] => return target.into(),
+    };
+    let arch = match arch {
+        "i386" | "i486" | "i586" | "i686" => "i386",
+        arch if arch.starts_with("armv") => "arm",
+        arch => arch,
+    };
+
+    format!("{}-{}", arch, rest)
+}
+
+/// The numeric version of a versioned library, such as `[3, 0]` for `libssl.so.3.0`.
+fn version_key(path: &Path, prefix: &str) -> Vec<u32> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(prefix))
+        .map(|version| {
+            version
+                .split('.')
+                .filter_map(|part| part.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A library found on disk.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Found {
+    /// The file.
+    pub(crate) path: PathBuf,
+
+    /// The kind of library, or `None` if it can't be told, such as for a `.lib` file of MSVC.
+    pub(crate) kind: Option<Kind>,
+
+    /// Whether or not it must be linked by its full file name, such as `libssl.so.3`.
+    pub(crate) verbatim: bool,
+}
+
+/// An error which occurred while locating a library.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// No suitable file was found.
+    NotFound {
+        /// The name of the library.
+        name: String,
+
+        /// The directories searched, in order.
+        searched: Vec<PathBuf>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound { name, searched } => {
+                write!(f, "library `{}` was not found in", name)?;
+
+                for (index, dir) in searched.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, dir.display())?
+                }
+
+                if searched.is_empty() {
+                    write!(f, " any directory")?
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::{multiarch, Error, Preference, Search};
+    use crate::cargo_rustc_link_lib::Kind;
+    use crate::utils::TempDir;
+    use std::path::{Path, PathBuf};
+
+    fn touch(dir: &Path, names: &[&str]) {
+        std::fs::create_dir_all(dir).unwrap();
+
+        for name in names {
+            std::fs::write(dir.join(name), "").unwrap()
+        }
+    }
+
+    fn search(preference: Preference) -> Search {
+        Search {
+            dirs: Vec::new(),
+            standard_dirs: Vec::new(),
+            target_os: "linux".into(),
+            target_env: "gnu".into(),
+            preference,
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = TempDir::new("library_find");
+        let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+        touch(
+            &first,
+            &[
+                "libssl.so.1.1",
+                "libssl.so.3",
+                "libssl.so.3.debug",
+                "libz.a",
+            ],
+        );
+        touch(&second, &["libssl.a", "libz.so"]);
+        let dirs = vec![first.clone(), second.clone()];
+
+        let found = search(Preference::PreferDynamic)
+            .find("ssl", &dirs)
+            .unwrap();
+        assert_eq!(found.path, first.join("libssl.so.3"));
+        assert_eq!(found.kind, Some(Kind::DynamicLibrary));
+        assert!(found.verbatim);
+        // the first directory holding the library wins, whatever the preference.
+        let found = search(Preference::PreferStatic).find("ssl", &dirs).unwrap();
+        assert_eq!(found.path, first.join("libssl.so.3"));
+        let found = search(Preference::PreferDynamic).find("z", &dirs).unwrap();
+        assert_eq!(found.path, first.join("libz.a"));
+        assert_eq!(found.kind, Some(Kind::Static));
+        let found = search(Preference::StaticOnly).find("ssl", &dirs).unwrap();
+        assert_eq!(found.path, second.join("libssl.a"));
+        assert_eq!(found.kind, Some(Kind::Static));
+
+        let mut static_only = search(Preference::StaticOnly);
+        static_only.dirs.push(first.clone());
+        let error = static_only.find("crypto", &[]).unwrap_err();
+        let expected = Error::NotFound {
+            name: "crypto".into(),
+            searched: vec![first.clone()],
+        };
+        assert_eq!(error, expected);
+        assert_eq!(
+            error.to_string(),
+            format!("library `crypto` was not found in {}", first.display())
+        );
+        let searched: Vec<PathBuf> = Vec::new();
+        assert!(search(Preference::StaticOnly)
+            .find("ssl", &searched)
+            .is_err())
+    }
+
+    #[test]
+    fn test_multiarch() {
+        assert_eq!(multiarch("x86_64-unknown-linux-gnu"), "x86_64-linux-gnu");
+        assert_eq!(multiarch("i686-unknown-linux-gnu"), "i386-linux-gnu");
+        assert_eq!(
+            multiarch("armv7-unknown-linux-gnueabihf"),
+            "arm-linux-gnueabihf"
+        );
+        assert_eq!(multiarch("aarch64-linux-android"), "aarch64-linux-android")
+    }
+
+    #[test]
+    fn test_from_env() {
+        let env = |vars: Vec<(&'static str, &'static str)>| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        let search = Search::from_env_in(&env(vec![
+            ("CARGO_CFG_TARGET_OS", "macos"),
+            ("TARGET", "aarch64-apple-darwin"),
+            ("HOST", "aarch64-apple-darwin"),
+        ]));
+        assert_eq!(search.dirs, vec![PathBuf::from("/opt/homebrew/lib")]);
+        assert!(!search
+            .standard_dirs
+            .contains(&PathBuf::from("/opt/homebrew/lib")));
+        let search = Search::from_env_in(&env(vec![
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("CARGO_CFG_TARGET_POINTER_WIDTH", "32"),
+            ("TARGET", "i686-unknown-linux-gnu"),
+            ("HOST", "i686-unknown-linux-gnu"),
+        ]));
+        assert_eq!(
+            search.standard_dirs[1],
+            PathBuf::from("/usr/lib/i386-linux-gnu")
+        );
+        let search = Search::from_env_in(&env(vec![
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("TARGET", "aarch64-unknown-linux-gnu"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+        ]));
+        assert!(search.dirs.is_empty() && search.standard_dirs.is_empty())
+    }
+
+    #[test]
+    fn test_find_windows() {
+        let dir = TempDir::new("library_find_windows");
+        touch(dir.path(), &["libssl.a", "libssl.dll.a", "crypto.lib"]);
+        let dirs = vec![dir.path().to_path_buf()];
+        let mut search = search(Preference::PreferDynamic);
+        search.target_os = "windows".into();
+
+        let found = search.find("ssl", &dirs).unwrap();
+        assert_eq!(found.path, dir.path().join("libssl.dll.a"));
+        assert_eq!(found.kind, Some(Kind::DynamicLibrary));
+        search.preference = Preference::StaticOnly;
+        let found = search.find("ssl", &dirs).unwrap();
+        assert_eq!(found.path, dir.path().join("libssl.a"));
+        assert!(search.find("crypto", &dirs).is_err());
+
+        search.target_env = "msvc".into();
+        let found = search.find("crypto", &dirs).unwrap();
+        assert_eq!(found.path, dir.path().join("crypto.lib"));
+        assert_eq!(found.kind, None);
+        assert!(search.find("ssl", &dirs).is_err())
+    }
+}
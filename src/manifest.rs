@@ -0,0 +1,235 @@
+//! A machine readable manifest of what a build script did, written to `OUT_DIR`.
+//! # Format
+//! The manifest is a JSON object, written to [`FILE_NAME`](FILE_NAME) by
+//! [`BuildScript::build()`](crate::BuildScript::build) once enabled through
+//! [`BuildScript::manifest()`](crate::BuildScript::manifest). Its fields are:
+//! - `version`: the version of the format, currently [`VERSION`](VERSION). It is bumped whenever
+//!   a field is removed or changes meaning; new fields may be added without bumping it.
+//! - `instructions`: every instruction written, in order, in the `serde` representation of
+//!   [`Instruction`](Instruction).
+//! - `env`: every environment variable tracked through `rerun-if-env-changed`.
+//! - `files`: every file tracked through `rerun-if-changed`.
+//! - `invocations`: every tool invocation recorded through
+//!   [`BuildScript::record_invocation()`](crate::BuildScript::record_invocation).
+//! - `started_at`: when the manifest was enabled, in milliseconds since the unix epoch.
+//! - `duration_ms`: the milliseconds between `started_at` and the manifest being written.
+//! # Notes
+//! This module is only available with the `manifest` feature.
+use crate::{Instruction, Prefix};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The version of the manifest format.
+pub const VERSION: u32 = 1;
+
+/// The name of the manifest in `OUT_DIR`.
+pub const FILE_NAME: &str = "build-script-manifest.json";
+
+/// A manifest of what a build script did.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the format.
+    pub version: u32,
+
+    /// Every instruction written, in order.
+    pub instructions: Vec<Instruction>,
+
+    /// Every environment variable tracked.
+    pub env: Vec<String>,
+
+    /// Every file tracked.
+    pub files: Vec<PathBuf>,
+
+    /// Every tool invocation recorded.
+    pub invocations: Vec<Invocation>,
+
+    /// When the manifest was enabled, in milliseconds since the unix epoch.
+    pub started_at: u64,
+
+    /// The milliseconds between `started_at` and the manifest being written.
+    pub duration_ms: u64,
+}
+
+/// A tool the build script ran, such as a compiler.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Invocation {
+    /// The program.
+    pub program: String,
+
+    /// The arguments passed to the program.
+    pub args: Vec<String>,
+
+    /// Whether or not the program exited successfully.
+    pub success: bool,
+
+    /// How long the program ran for, in milliseconds.
+    pub duration_ms: u64,
+}
+
+impl Invocation {
+    /// Run `command` to completion, collecting its output and recording it as an invocation.
+    pub fn output(command: &mut Command) -> io::Result<(Output, Self)> {
+        let started = Instant::now();
+        let output = command.output()?;
+        let invocation = Self {
+            program: command.get_program().to_string_lossy().into(),
+            args: command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into())
+                .collect(),
+            success: output.status.success(),
+            duration_ms: millis(started.elapsed()),
+        };
+
+        Ok((output, invocation))
+    }
+}
+
+/// Convert a duration to milliseconds.
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+/// Records a [`Manifest`](Manifest) as a build script runs.
+pub(crate) struct Recorder {
+    /// Where the manifest will be written.
+    path: PathBuf,
+
+    /// The manifest so far.
+    manifest: Manifest,
+
+    /// When the recorder was created.
+    started: Instant,
+}
+
+impl Recorder {
+    /// Create a new recorder writing to `path`.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(millis)
+            .unwrap_or_default();
+
+        Self {
+            path,
+            manifest: Manifest {
+                version: VERSION,
+                instructions: Vec::new(),
+                env: Vec::new(),
+                files: Vec::new(),
+                invocations: Vec::new(),
+                started_at,
+                duration_ms: 0,
+            },
+            started: Instant::now(),
+        }
+    }
+
+    /// Record a written instruction.
+    pub(crate) fn instruction(&mut self, instruction: &Instruction) {
+        let manifest = &mut self.manifest;
+
+        if let (Prefix::Cargo, Some(name)) = (&instruction.prefix, &instruction.name) {
+            let value = instruction.value.to_string();
+
+            match name.as_str() {
+                "rerun-if-env-changed" if !manifest.env.contains(&value) => {
+                    manifest.env.push(value)
+                }
+                "rerun-if-changed" => {
+                    let path = PathBuf::from(value);
+
+                    if !manifest.files.contains(&path) {
+                        manifest.files.push(path)
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        manifest.instructions.push(instruction.clone())
+    }
+
+    /// Record a tool invocation.
+    pub(crate) fn invocation(&mut self, invocation: Invocation) {
+        self.manifest.invocations.push(invocation)
+    }
+
+    /// Write the manifest.
+    pub(crate) fn write(&mut self) -> io::Result<()> {
+        self.manifest.duration_ms = millis(self.started.elapsed());
+        let json = serde_json::to_string_pretty(&self.manifest)?;
+
+        fs::write(&self.path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Invocation, Manifest, FILE_NAME, VERSION};
+    use crate::utils::TempDir;
+    use crate::{BuildScript, Error};
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_manifest() {
+        let dir =
+            std::env::temp_dir().join(format!("build_script_manifest_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .manifest_to(path.clone())
+            .cargo_rerun_if_changed("build.rs".into())
+            .cargo_rerun_if_changed("build.rs".into())
+            .cargo_rerun_if_env_changed("CC")
+            .cargo_rustc_link_lib(None, "ssl")
+            .record_invocation(Invocation {
+                program: "cc".into(),
+                args: vec!["-c".into(), "foo.c".into()],
+                success: true,
+                duration_ms: 12,
+            })
+            .build();
+        let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(manifest.version, VERSION);
+        assert_eq!(manifest.instructions.len(), 4);
+        assert_eq!(manifest.env, vec!["CC"]);
+        assert_eq!(manifest.files, vec![std::path::PathBuf::from("build.rs")]);
+        assert_eq!(manifest.invocations[0].program, "cc");
+        assert!(manifest.started_at > 0)
+    }
+
+    #[test]
+    fn test_write_error() {
+        let dir = TempDir::new("manifest_write_error");
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script
+            .manifest_to(dir.path().join("missing").join(FILE_NAME))
+            .cargo_rustc_link_lib(None, "ssl");
+        assert!(matches!(build_script.try_build(), Err(Error::Manifest(_))));
+        assert_eq!(build_script.instructions().count(), 0);
+        drop(build_script);
+        assert_eq!(writer, b"cargo:rustc-link-lib=ssl\n")
+    }
+
+    #[test]
+    fn test_invocation_output() {
+        let mut command = Command::new("rustc");
+        command.arg("--version");
+        let (output, invocation) = Invocation::output(&mut command).unwrap();
+        assert!(output.status.success());
+        assert!(invocation.success);
+        assert_eq!(invocation.program, "rustc");
+        assert_eq!(invocation.args, vec!["--version"])
+    }
+}
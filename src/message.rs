@@ -0,0 +1,428 @@
+//! Parsing for the `build-script-executed` messages emitted by `cargo --message-format=json`.
+//! # Notes
+//! This module is only available with the `message` feature.
+use crate::cargo_rustc_link_lib;
+use crate::cargo_rustc_link_search;
+use crate::{Instruction, Value};
+use serde::Deserialize;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+
+/// The `reason` of a `build-script-executed` message.
+const REASON: &str = "build-script-executed";
+
+/// A `build-script-executed` message. This is what cargo recorded from a build script's output.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BuildScriptExecuted {
+    /// The package id of the package the build script belongs to.
+    pub package_id: String,
+
+    /// The libraries linked by the build script (`rustc-link-lib`).
+    pub linked_libs: Vec<LinkedLib>,
+
+    /// The library search paths added by the build script (`rustc-link-search`).
+    pub linked_paths: Vec<LinkedPath>,
+
+    /// The `cfg` settings enabled by the build script (`rustc-cfg`).
+    pub cfgs: Vec<Cfg>,
+
+    /// The environment variables set by the build script (`rustc-env`).
+    pub env: Vec<(String, String)>,
+
+    /// The `OUT_DIR` of the build script.
+    pub out_dir: PathBuf,
+}
+
+impl BuildScriptExecuted {
+    /// Convert the message back into the [`Instruction`](Instruction)s which produced it.
+    /// Libraries come first, then search paths, `cfg`s and environment variables.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let linked_libs = self.linked_libs.iter().map(LinkedLib::instruction);
+        let linked_paths = self.linked_paths.iter().map(LinkedPath::instruction);
+        let cfgs = self.cfgs.iter().map(Cfg::instruction);
+        let env = self.env.iter().map(|(var, value)| {
+            Instruction::new(
+                "rustc-env",
+                Value::UnquotedMapping(var.clone(), value.clone()),
+            )
+        });
+
+        linked_libs
+            .chain(linked_paths)
+            .chain(cfgs)
+            .chain(env)
+            .collect()
+    }
+}
+
+/// A library linked by a build script, such as `static=foo`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkedLib {
+    /// The kind of the library, if any.
+    pub kind: Option<cargo_rustc_link_lib::Kind>,
+
+    /// The linking modifiers after the kind, such as `+whole-archive` in
+    /// `static:+whole-archive=foo`.
+    pub modifiers: Option<String>,
+
+    /// The name of the library.
+    pub name: String,
+}
+
+impl LinkedLib {
+    /// Parse a library in the `[KIND[:MODIFIERS]=]NAME` format.
+    pub fn parse(string: &str) -> Result<Self, Error> {
+        let (kind, name) = match string.split_once('=') {
+            Some((kind, name)) => (Some(kind), name),
+            None => (None, string),
+        };
+        let (kind, modifiers) = match kind.map(|kind| kind.split_once(':')) {
+            Some(Some((kind, modifiers))) => (Some(kind), Some(modifiers.into())),
+            Some(None) => (kind, None),
+            None => (None, None),
+        };
+        let kind = kind
+            .map(str::parse)
+            .transpose()
+            .map_err(Error::LinkLibKind)?;
+
+        Ok(Self {
+            kind,
+            modifiers,
+            name: name.into(),
+        })
+    }
+
+    /// Convert this into a `rustc-link-lib` [`Instruction`](Instruction).
+    pub fn instruction(&self) -> Instruction {
+        let kind = self.kind.map(|kind| {
+            let kind: &str = kind.into();
+
+            match &self.modifiers {
+                Some(modifiers) => format!("{}:{}", kind, modifiers),
+                None => kind.into(),
+            }
+        });
+
+        Instruction::new(
+            "rustc-link-lib",
+            Value::UnquotedOptionalKey(kind, self.name.clone()),
+        )
+    }
+}
+
+/// A library search path added by a build script, such as `native=/usr/lib`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkedPath {
+    /// The kind of the search path, if any.
+    pub kind: Option<cargo_rustc_link_search::Kind>,
+
+    /// The search path.
+    pub path: PathBuf,
+}
+
+impl LinkedPath {
+    /// Parse a search path in the `[KIND=]PATH` format.
+    pub fn parse(string: &str) -> Result<Self, Error> {
+        // paths may contain `=`, so only treat the prefix as a kind if it is a known one.
+        if let Some((kind, path)) = string.split_once('=') {
+            if let Ok(kind) = kind.parse() {
+                return Ok(Self {
+                    kind: Some(kind),
+                    path: path.into(),
+                });
+            }
+        }
+
+        Ok(Self {
+            kind: None,
+            path: string.into(),
+        })
+    }
+
+    /// Convert this into a `rustc-link-search` [`Instruction`](Instruction).
+    pub fn instruction(&self) -> Instruction {
+        Instruction::new(
+            "rustc-link-search",
+            Value::UnquotedOptionalKey(self.kind.map(Into::into), self.path.display().to_string()),
+        )
+    }
+}
+
+/// A `cfg` setting enabled by a build script, such as `feature="foo"`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cfg {
+    /// The key of the `cfg`.
+    pub key: String,
+
+    /// The value of the `cfg`, if any.
+    pub value: Option<String>,
+}
+
+impl Cfg {
+    /// Parse a `cfg` in the `KEY[="VALUE"]` format.
+    pub fn parse(string: &str) -> Result<Self, Error> {
+        match string.split_once('=') {
+            Some((key, value)) => {
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .ok_or_else(|| Error::Cfg(string.into()))?;
+
+                Ok(Self {
+                    key: key.into(),
+                    value: Some(value.into()),
+                })
+            }
+            None => Ok(Self {
+                key: string.into(),
+                value: None,
+            }),
+        }
+    }
+
+    /// Convert this into a `rustc-cfg` [`Instruction`](Instruction).
+    pub fn instruction(&self) -> Instruction {
+        Instruction::new(
+            "rustc-cfg",
+            Value::OptionalValue(self.key.clone(), self.value.clone()),
+        )
+    }
+}
+
+/// The message as cargo writes it.
+#[derive(Deserialize)]
+struct RawMessage {
+    reason: String,
+    #[serde(default)]
+    package_id: String,
+    #[serde(default)]
+    linked_libs: Vec<String>,
+    #[serde(default)]
+    linked_paths: Vec<String>,
+    #[serde(default)]
+    cfgs: Vec<String>,
+    #[serde(default)]
+    env: Vec<(String, String)>,
+    #[serde(default)]
+    out_dir: PathBuf,
+}
+
+/// Parse a single line of cargo's JSON output. This returns [`None`](None) if the line is a
+/// message other than `build-script-executed`.
+pub fn parse(line: &str) -> Result<Option<BuildScriptExecuted>, Error> {
+    let message: RawMessage = serde_json::from_str(line).map_err(Error::Json)?;
+
+    if message.reason != REASON {
+        return Ok(None);
+    }
+
+    Ok(Some(BuildScriptExecuted {
+        package_id: message.package_id,
+        linked_libs: message
+            .linked_libs
+            .iter()
+            .map(|lib| LinkedLib::parse(lib))
+            .collect::<Result<_, _>>()?,
+        linked_paths: message
+            .linked_paths
+            .iter()
+            .map(|path| LinkedPath::parse(path))
+            .collect::<Result<_, _>>()?,
+        cfgs: message
+            .cfgs
+            .iter()
+            .map(|cfg| Cfg::parse(cfg))
+            .collect::<Result<_, _>>()?,
+        env: message.env,
+        out_dir: message.out_dir,
+    }))
+}
+
+/// Parse every `build-script-executed` message in cargo's JSON output, skipping empty lines and
+/// other messages.
+pub fn parse_stream(reader: impl BufRead) -> Result<Vec<BuildScriptExecuted>, Error> {
+    let mut messages = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.map_err(Error::Io)?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(message) = parse(&line)? {
+            messages.push(message)
+        }
+    }
+
+    Ok(messages)
+}
+
+/// An error which occurred while parsing cargo's JSON output.
+#[derive(Debug)]
+pub enum Error {
+    /// The output couldn't be read.
+    Io(io::Error),
+
+    /// A line wasn't a valid message.
+    Json(serde_json::Error),
+
+    /// A linked library had an unknown kind.
+    LinkLibKind(cargo_rustc_link_lib::ParseKindError),
+
+    /// A `cfg` had a value which wasn't quoted.
+    Cfg(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read cargo output: {}", error),
+            Self::Json(error) => write!(f, "invalid cargo message: {}", error),
+            Self::LinkLibKind(error) => write!(f, "invalid linked library: {}", error),
+            Self::Cfg(cfg) => write!(f, "invalid cfg `{}`: value must be quoted", cfg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Json(error) => Some(error),
+            Self::LinkLibKind(error) => Some(error),
+            Self::Cfg(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildScriptExecuted, Cfg, LinkedLib, LinkedPath};
+    use crate::cargo_rustc_link_lib::Kind as LibKind;
+    use crate::cargo_rustc_link_search::Kind as SearchKind;
+
+    const STREAM: &str = include_str!("../tests/fixtures/build-script-executed.jsonl");
+    const INVALID_KIND: &str = include_str!("../tests/fixtures/invalid-link-kind.json");
+
+    fn messages() -> Vec<BuildScriptExecuted> {
+        super::parse_stream(STREAM.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_skips_other_reasons() {
+        let line = r#"{"reason":"compiler-artifact","package_id":"foo 0.1.0"}"#;
+        assert!(super::parse(line).unwrap().is_none())
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let messages = messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].package_id,
+            "openssl-sys 0.9.72 (registry+https://github.com/rust-lang/crates.io-index)"
+        );
+        assert_eq!(
+            messages[1].package_id,
+            "libz-sys 1.1.8 (registry+https://github.com/rust-lang/crates.io-index)"
+        );
+    }
+
+    #[test]
+    fn test_linked_libs() {
+        let messages = messages();
+        let expected = vec![
+            LinkedLib {
+                kind: None,
+                modifiers: None,
+                name: "ssl".into(),
+            },
+            LinkedLib {
+                kind: Some(LibKind::DynamicLibrary),
+                modifiers: None,
+                name: "crypto".into(),
+            },
+        ];
+        assert_eq!(messages[0].linked_libs, expected);
+        let expected = vec![LinkedLib {
+            kind: Some(LibKind::Static),
+            modifiers: Some("+whole-archive".into()),
+            name: "z".into(),
+        }];
+        assert_eq!(messages[1].linked_libs, expected);
+    }
+
+    #[test]
+    fn test_linked_paths() {
+        let messages = messages();
+        let expected = vec![
+            LinkedPath {
+                kind: Some(SearchKind::Native),
+                path: "/usr/lib/x86_64-linux-gnu".into(),
+            },
+            LinkedPath {
+                kind: None,
+                path: "/opt/key=value".into(),
+            },
+        ];
+        assert_eq!(messages[0].linked_paths, expected)
+    }
+
+    #[test]
+    fn test_cfgs_and_env() {
+        let messages = messages();
+        let expected = vec![
+            Cfg {
+                key: "ossl300".into(),
+                value: None,
+            },
+            Cfg {
+                key: "osslconf".into(),
+                value: Some("OPENSSL_NO_SSL3_METHOD".into()),
+            },
+        ];
+        assert_eq!(messages[0].cfgs, expected);
+        assert_eq!(
+            messages[0].env,
+            vec![("OPENSSL_VERSION".to_string(), "300".to_string())]
+        );
+        assert_eq!(
+            messages[0].out_dir,
+            std::path::PathBuf::from("/target/debug/build/openssl-sys-5a7a2a0a/out")
+        );
+    }
+
+    #[test]
+    fn test_instructions() {
+        let instructions: Vec<String> = messages()[0]
+            .instructions()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let expected = vec![
+            "cargo:rustc-link-lib=ssl",
+            "cargo:rustc-link-lib=dylib=crypto",
+            "cargo:rustc-link-search=native=/usr/lib/x86_64-linux-gnu",
+            "cargo:rustc-link-search=/opt/key=value",
+            "cargo:rustc-cfg=ossl300",
+            "cargo:rustc-cfg=osslconf=\"OPENSSL_NO_SSL3_METHOD\"",
+            "cargo:rustc-env=OPENSSL_VERSION=300",
+        ];
+        assert_eq!(instructions, expected);
+        let instruction = messages()[1].linked_libs[0].instruction().to_string();
+        assert_eq!(instruction, "cargo:rustc-link-lib=static:+whole-archive=z")
+    }
+
+    #[test]
+    fn test_invalid_kind() {
+        assert!(super::parse(INVALID_KIND).is_err())
+    }
+
+    #[test]
+    fn test_unquoted_cfg() {
+        assert!(Cfg::parse("key=value").is_err())
+    }
+}
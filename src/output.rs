@@ -0,0 +1,219 @@
+//! How instructions are written. See
+//! [`BuildScript::output_format()`](crate::BuildScript::output_format).
+//! # Examples
+//! A format for a wrapper which only wants the `rustc-env` variables, as a shell script:
+//! ```rust
+//! use build_script::output::OutputFormat;
+//! use build_script::{BuildScript, Instruction, Value};
+//!
+//! struct Shell;
+//!
+//! impl OutputFormat for Shell {
+//!     fn format(&mut self, instruction: &Instruction) -> Option<String> {
+//!         match (instruction.name.as_deref(), &instruction.value) {
+//!             (Some("rustc-env"), Value::UnquotedMapping(var, value)) => {
+//!                 Some(format!("export {}='{}'", var, value.replace('\'', r"'\''")))
+//!             }
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! let mut build_script = BuildScript::default();
+//! build_script.output_format(Shell);
+//! build_script.cargo_rustc_env("VERSION", "1.0");
+//! build_script.build();
+//! ```
+use crate::explain;
+use crate::{Instruction, Prefix, Value};
+
+/// Renders instructions for whatever reads the output of the build script.
+pub trait OutputFormat {
+    /// Render an instruction as a single line, without the newline. Returns [`None`](None) if
+    /// the instruction shouldn't be written at all.
+    fn format(&mut self, instruction: &Instruction) -> Option<String>;
+}
+
+/// Cargo's single colon syntax, such as `cargo:rustc-link-lib=z`, which every version of cargo
+/// understands. This is the default.
+/// # Notes
+/// The single colon syntax has no `error` instruction, and would pass one as metadata, so it is
+/// written as `cargo:warning=error: MESSAGE` instead.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct CargoLegacy;
+
+impl OutputFormat for CargoLegacy {
+    fn format(&mut self, instruction: &Instruction) -> Option<String> {
+        match (&instruction.prefix, instruction.name.as_deref()) {
+            (Prefix::Cargo, Some("error")) => {
+                Some(format!("cargo:warning=error: {}", instruction.value))
+            }
+            _ => Some(instruction.to_string()),
+        }
+    }
+}
+
+/// Cargo's double colon syntax, such as `cargo::rustc-link-lib=z`, which requires cargo 1.77 or
+/// newer.
+/// # Notes
+/// Metadata, which the single colon syntax passes as any unknown instruction, is written as
+/// `cargo::metadata=KEY=VALUE`. `rustc-cdylib-link-arg` is written as `rustc-link-arg-cdylib`.
+/// Instructions with a custom prefix are written as is.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct CargoModern;
+
+impl OutputFormat for CargoModern {
+    fn format(&mut self, instruction: &Instruction) -> Option<String> {
+        /// The instructions the double colon syntax knows.
+        const KNOWN: &[&str] = &[
+            "rerun-if-changed",
+            "rerun-if-env-changed",
+            "rustc-link-arg",
+            "rustc-link-arg-bin",
+            "rustc-link-arg-bins",
+            "rustc-link-arg-tests",
+            "rustc-link-arg-examples",
+            "rustc-link-arg-benches",
+            "rustc-link-arg-cdylib",
+            "rustc-link-lib",
+            "rustc-link-search",
+            "rustc-flags",
+            "rustc-cfg",
+            "rustc-check-cfg",
+            "rustc-env",
+            "warning",
+            "error",
+            "metadata",
+        ];
+
+        if let Prefix::Custom(_) = instruction.prefix {
+            return Some(instruction.to_string());
+        }
+
+        let line = match instruction.name.as_deref() {
+            Some("rustc-cdylib-link-arg") => {
+                format!("cargo::rustc-link-arg-cdylib={}", instruction.value)
+            }
+            Some(name) if KNOWN.contains(&name) => format!("cargo::{}={}", name, instruction.value),
+            Some(name) => format!("cargo::metadata={}={}", name, instruction.value),
+            None => format!("cargo::metadata={}", value(instruction)),
+        };
+
+        Some(line)
+    }
+}
+
+/// A JSON object per line, in the same form as the `serde` implementation of
+/// [`Instruction`](Instruction) used by the manifest, such as
+/// `{"prefix":"cargo","name":"rustc-link-lib","value":{"unquoted_optional_key":["static","z"]}}`.
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct JsonLines;
+
+#[cfg(feature = "serde")]
+impl OutputFormat for JsonLines {
+    fn format(&mut self, instruction: &Instruction) -> Option<String> {
+        Some(serde_json::to_string(instruction).expect("an instruction is always valid json"))
+    }
+}
+
+/// The instruction and a plain-English explanation of it, such as
+/// ``rustc-link-lib=z: Cargo passes `-l z` to rustc, [...]``. See [`explain`](explain).
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Human;
+
+impl OutputFormat for Human {
+    fn format(&mut self, instruction: &Instruction) -> Option<String> {
+        let name = match &instruction.name {
+            Some(name) => format!("{}={}", name, instruction.value),
+            None => value(instruction),
+        };
+
+        Some(format!("{}: {}", name, explain::explain(instruction)))
+    }
+}
+
+/// The value of an instruction as it appears after the name, or the whole mapping if it has no
+/// name.
+fn value(instruction: &Instruction) -> String {
+    match (&instruction.name, &instruction.value) {
+        (None, Value::Mapping(key, value)) | (None, Value::UnquotedMapping(key, value)) => {
+            format!("{}={}", key, value)
+        }
+        (_, value) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CargoLegacy, CargoModern, Human, OutputFormat};
+    use crate::{Instruction, Prefix, Value};
+
+    fn instructions() -> Vec<Instruction> {
+        let mut custom = Instruction::new("thing", Value::Singular("value".into()));
+        custom.prefix(Prefix::Custom("other".into()));
+
+        vec![
+            Instruction::new(
+                "rustc-link-lib",
+                Value::UnquotedOptionalKey(Some("static".into()), "z".into()),
+            ),
+            Instruction::new("rustc-cdylib-link-arg", Value::Singular("-s".into())),
+            Instruction::new("include", Value::Singular("/opt/z/include".into())),
+            Instruction::new_mapping(Value::UnquotedMapping("root".into(), "/opt/z".into())),
+            Instruction::new("warning", Value::Singular("say \"hi\"\n".into())),
+            custom,
+            Instruction::new("error", Value::Singular("no zlib".into())),
+        ]
+    }
+
+    fn format(mut format: impl OutputFormat) -> Vec<String> {
+        instructions()
+            .iter()
+            .filter_map(|instruction| format.format(instruction))
+            .collect()
+    }
+
+    #[test]
+    fn test_cargo() {
+        let mut legacy: Vec<_> = instructions().iter().map(ToString::to_string).collect();
+        *legacy.last_mut().unwrap() = "cargo:warning=error: no zlib".into();
+        assert_eq!(format(CargoLegacy), legacy);
+        let expected = vec![
+            "cargo::rustc-link-lib=static=z",
+            "cargo::rustc-link-arg-cdylib=-s",
+            "cargo::metadata=include=/opt/z/include",
+            "cargo::metadata=root=/opt/z",
+            "cargo::warning=say \"hi\"\n",
+            "other:thing=value",
+            "cargo::error=no zlib",
+        ];
+        assert_eq!(format(CargoModern), expected)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_json_lines() {
+        let formatted = format(super::JsonLines);
+        let expected = vec![
+            r#"{"prefix":"cargo","name":"rustc-link-lib","value":{"unquoted_optional_key":["static","z"]}}"#,
+            r#"{"prefix":"cargo","name":null,"value":{"unquoted_mapping":["root","/opt/z"]}}"#,
+            r#"{"prefix":"cargo","name":"warning","value":{"singular":"say \"hi\"\n"}}"#,
+        ];
+        let picked = vec![&formatted[0], &formatted[3], &formatted[4]];
+        assert_eq!(picked, expected);
+
+        for (line, instruction) in formatted.iter().zip(instructions()) {
+            let parsed: crate::Instruction = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed, instruction)
+        }
+    }
+
+    #[test]
+    fn test_human() {
+        let formatted = format(Human);
+        assert!(formatted[0].starts_with("rustc-link-lib=static=z: Cargo passes `-l static=z`"));
+        assert!(formatted[3].starts_with("root=/opt/z: Cargo passes the metadata"))
+    }
+}
@@ -0,0 +1,946 @@
+//! Resolving libraries through pkg-config `.pc` files, without needing the `pkg-config` binary.
+//! # Notes
+//! `.pc` files are searched for in `PKG_CONFIG_PATH`, then in `PKG_CONFIG_LIBDIR`, or in the
+//! default directories if it isn't set. `PKG_CONFIG_SYSROOT_DIR` is prepended to every `-L` and
+//! `-I` path, like `pkg-config` does. `Requires` are always followed, while `Requires.private` are
+//! only followed for include paths, unless linking statically.
+//!
+//! Every variable can be set for a single target, such as `PKG_CONFIG_PATH_aarch64-linux-android`
+//! or `PKG_CONFIG_PATH_aarch64_linux_android`, which takes precedence. When cross-compiling, the
+//! default directories hold packages of the host, so they are only searched if
+//! `PKG_CONFIG_ALLOW_CROSS` is set, unless `PKG_CONFIG_LIBDIR` or `PKG_CONFIG_SYSROOT_DIR` is.
+//! # Examples
+//! ```rust,no_run
+//! use build_script::BuildScript;
+//!
+//! let mut build_script = BuildScript::default();
+//! let library = build_script.pkg_config("openssl", Some(">= 1.1")).unwrap();
+//! println!("found openssl {}", library.version);
+//! build_script.build();
+//! ```
+use crate::library;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io};
+
+/// The environment variables which affect resolving. Each can also be set for a single target,
+/// see [`env_vars()`](env_vars).
+pub const ENV: &[&str] = &[
+    "PKG_CONFIG_PATH",
+    "PKG_CONFIG_LIBDIR",
+    "PKG_CONFIG_SYSROOT_DIR",
+    "PKG_CONFIG_ALL_STATIC",
+    "PKG_CONFIG_ALLOW_CROSS",
+];
+
+/// The names of a variable of [`ENV`](ENV) for `target`, most specific first.
+fn targeted(var: &str, target: Option<&str>) -> Vec<String> {
+    let mut names = Vec::new();
+
+    if let Some(target) = target {
+        names.push(format!("{}_{}", var, target));
+        names.push(format!("{}_{}", var, target.replace('-', "_")));
+    }
+
+    names.push(var.into());
+    names
+}
+
+/// Every environment variable which affects resolving for the target in `TARGET`, including the
+/// target specific variants of [`ENV`](ENV).
+pub fn env_vars() -> Vec<String> {
+    let target = env::var("TARGET").ok();
+
+    ENV.iter()
+        .flat_map(|var| targeted(var, target.as_deref()))
+        .collect()
+}
+
+/// The directories searched when `PKG_CONFIG_LIBDIR` isn't set.
+const DEFAULT_DIRS: &[&str] = &[
+    "/usr/local/lib/pkgconfig",
+    "/usr/local/share/pkgconfig",
+    "/usr/lib64/pkgconfig",
+    "/usr/lib/pkgconfig",
+    "/usr/share/pkgconfig",
+];
+
+/// How libraries are resolved.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Config {
+    /// The directories searched for `.pc` files, in order.
+    pub search_paths: Vec<PathBuf>,
+
+    /// Prepended to every `-L` and `-I` path.
+    pub sysroot: Option<PathBuf>,
+
+    /// Whether or not to resolve for static linking, which also follows `Requires.private` and
+    /// `Libs.private`.
+    pub statik: bool,
+}
+
+impl Config {
+    /// Resolve like `pkg-config` does, from `PKG_CONFIG_PATH`, `PKG_CONFIG_LIBDIR` and
+    /// `PKG_CONFIG_SYSROOT_DIR`. Static linking is enabled if `PKG_CONFIG_ALL_STATIC` is set.
+    /// # Errors
+    /// Fails if `HOST` and `TARGET` differ, but neither `PKG_CONFIG_LIBDIR`,
+    /// `PKG_CONFIG_SYSROOT_DIR` nor `PKG_CONFIG_ALLOW_CROSS` is set, as the default directories
+    /// would only find packages of the host.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_env_in(&|var| env::var(var).ok())
+    }
+
+    /// [`from_env()`](Self::from_env), reading variables through `var`.
+    fn from_env_in(var: &dyn Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let target = var("TARGET");
+        let paths = |name: &str| {
+            targeted(name, target.as_deref())
+                .iter()
+                .find_map(|name| var(name).filter(|paths| !paths.is_empty()))
+        };
+        let mut search_paths: Vec<PathBuf> = paths("PKG_CONFIG_PATH")
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        let sysroot = paths("PKG_CONFIG_SYSROOT_DIR").map(PathBuf::from);
+
+        match paths("PKG_CONFIG_LIBDIR") {
+            Some(libdir) => search_paths.extend(env::split_paths(&libdir)),
+            None => {
+                if let (Some(host), Some(target)) = (var("HOST"), &target) {
+                    if host != *target
+                        && sysroot.is_none()
+                        && paths("PKG_CONFIG_ALLOW_CROSS").is_none()
+                    {
+                        return Err(Error::CrossCompile {
+                            host,
+                            target: target.clone(),
+                        });
+                    }
+                }
+
+                if let Some(target) = &target {
+                    let multiarch = library::multiarch(target);
+                    search_paths.push(format!("/usr/lib/{}/pkgconfig", multiarch).into())
+                }
+
+                search_paths.extend(DEFAULT_DIRS.iter().map(PathBuf::from))
+            }
+        }
+
+        Ok(Self {
+            search_paths,
+            sysroot,
+            statik: paths("PKG_CONFIG_ALL_STATIC").is_some(),
+        })
+    }
+
+    /// Find the `.pc` file of a package.
+    fn find(&self, name: &str) -> Result<PathBuf, Error> {
+        let file_name = format!("{}.pc", name);
+
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| Error::NotFound(name.into()))
+    }
+
+    /// Resolve a library, and everything it requires. `constraint` is a version constraint such
+    /// as `>= 1.1`.
+    pub fn probe(&self, name: &str, constraint: Option<&str>) -> Result<Library, Error> {
+        let dependency = match constraint {
+            Some(constraint) => {
+                let mut dependencies = Dependency::parse_list(&format!("{} {}", name, constraint))?;
+
+                match dependencies.len() {
+                    1 => dependencies.remove(0),
+                    _ => return Err(Error::Constraint(constraint.into())),
+                }
+            }
+            None => Dependency {
+                name: name.into(),
+                constraint: None,
+            },
+        };
+        let mut resolver = Resolver {
+            config: self,
+            visited: BTreeSet::new(),
+            resolving: Vec::new(),
+            library: Library {
+                name: name.into(),
+                version: String::new(),
+                link_paths: Vec::new(),
+                libs: Vec::new(),
+                link_args: Vec::new(),
+                include_paths: Vec::new(),
+                files: Vec::new(),
+            },
+        };
+        let version = resolver.resolve(&dependency, true)?;
+        let mut library = resolver.library;
+        library.version = version;
+        library.libs = dedup_last(library.libs);
+
+        Ok(library)
+    }
+}
+
+/// A resolved library.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Library {
+    /// The name of the package.
+    pub name: String,
+
+    /// The version of the package.
+    pub version: String,
+
+    /// The `-L` paths, in order.
+    pub link_paths: Vec<PathBuf>,
+
+    /// The `-l` libraries, in link order. `-framework` libraries are prefixed with `framework=`.
+    pub libs: Vec<String>,
+
+    /// Every other flag of `Libs`, such as `-pthread` or `-Wl,--as-needed`, in order.
+    pub link_args: Vec<String>,
+
+    /// The `-I` paths, in order.
+    pub include_paths: Vec<PathBuf>,
+
+    /// Every `.pc` file used.
+    pub files: Vec<PathBuf>,
+}
+
+/// A version comparison.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Op {
+    /// `<`.
+    Lt,
+
+    /// `<=`.
+    Le,
+
+    /// `=`.
+    Eq,
+
+    /// `!=`.
+    Ne,
+
+    /// `>=`.
+    Ge,
+
+    /// `>`.
+    Gt,
+}
+
+impl Op {
+    /// Parse an operator.
+    fn parse(op: &str) -> Option<Self> {
+        let op = match op {
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            "=" => Self::Eq,
+            "!=" => Self::Ne,
+            ">=" => Self::Ge,
+            ">" => Self::Gt,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    /// Returns `true` if a comparison of a version to the required version satisfies this.
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Ge => ordering != Ordering::Less,
+            Self::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Ge => ">=",
+            Self::Gt => ">",
+        };
+
+        write!(f, "{}", op)
+    }
+}
+
+/// A required package, such as `zlib >= 1.2`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Dependency {
+    /// The name of the package.
+    pub name: String,
+
+    /// The version constraint, if any.
+    pub constraint: Option<(Op, String)>,
+}
+
+impl Dependency {
+    /// Parse a `Requires` list, such as `zlib >= 1.2, libssl`. The operators don't need to be
+    /// surrounded by spaces, so `zlib>=1.2` is accepted too.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, Error> {
+        let is_op = |c: char| matches!(c, '<' | '>' | '=' | '!');
+        let mut dependencies = Vec::new();
+        let mut tokens = list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .flat_map(|word| {
+                // split `zlib>=1.2` into `zlib`, `>=` and `1.2`.
+                let mut tokens = Vec::new();
+                let mut rest = word;
+
+                while let Some(c) = rest.chars().next() {
+                    let end = rest
+                        .find(|other: char| is_op(other) != is_op(c))
+                        .unwrap_or(rest.len());
+                    tokens.push(&rest[..end]);
+                    rest = &rest[end..];
+                }
+
+                tokens
+            })
+            .peekable();
+
+        while let Some(name) = tokens.next() {
+            if name.starts_with(is_op) {
+                return Err(Error::Constraint(list.into()));
+            }
+
+            let constraint = match tokens.peek().and_then(|token| Op::parse(token)) {
+                Some(op) => {
+                    tokens.next();
+                    let version = tokens
+                        .next()
+                        .ok_or_else(|| Error::Constraint(list.into()))?;
+
+                    Some((op, version.to_string()))
+                }
+                None => None,
+            };
+
+            dependencies.push(Self {
+                name: name.into(),
+                constraint,
+            })
+        }
+
+        Ok(dependencies)
+    }
+
+    /// Returns `true` if `version` satisfies the constraint.
+    pub fn accepts(&self, version: &str) -> bool {
+        match &self.constraint {
+            Some((op, required)) => op.accepts(compare_versions(version, required)),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.constraint {
+            Some((op, version)) => write!(f, "{} {} {}", self.name, op, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Compare versions like `pkg-config` does: numeric segments numerically, alphabetic segments
+/// lexically, and a numeric segment is newer than an alphabetic one.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    /// Split a version into its numeric and alphabetic segments.
+    fn segments(version: &str) -> Vec<&str> {
+        let mut segments = Vec::new();
+        let mut rest = version;
+
+        while !rest.is_empty() {
+            rest = rest.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+            let numeric = rest.starts_with(|c: char| c.is_ascii_digit());
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() || c.is_ascii_digit() != numeric)
+                .unwrap_or(rest.len());
+
+            if end > 0 {
+                segments.push(&rest[..end])
+            }
+
+            rest = &rest[end..]
+        }
+
+        segments
+    }
+
+    let (a, b) = (segments(a), segments(b));
+
+    for (a, b) in a.iter().zip(&b) {
+        let numeric = |segment: &str| segment.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = match (numeric(a), numeric(b)) {
+            (true, true) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.cmp(b),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a.len().cmp(&b.len())
+}
+
+/// A parsed `.pc` file.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct Package {
+    version: String,
+    requires: Vec<Dependency>,
+    requires_private: Vec<Dependency>,
+    libs: Vec<String>,
+    libs_private: Vec<String>,
+    cflags: Vec<String>,
+}
+
+impl Package {
+    /// Parse a `.pc` file, substituting variables. `pcfiledir` is predefined as the directory of
+    /// the file, and `pc_sysrootdir` as the sysroot.
+    fn parse(contents: &str, path: &Path, sysroot: Option<&Path>) -> Result<Self, Error> {
+        let parse_error = |message: String| Error::Parse(path.into(), message);
+        let mut variables = BTreeMap::new();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        variables.insert("pcfiledir".to_string(), dir.display().to_string());
+        let sysroot = sysroot.map(|sysroot| sysroot.display().to_string());
+        variables.insert(
+            "pc_sysrootdir".into(),
+            sysroot.unwrap_or_else(|| "/".into()),
+        );
+        let mut package = Self::default();
+
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let separator = line.find(['=', ':']);
+            let (key, separator, value) = match separator {
+                Some(index) => (
+                    line[..index].trim(),
+                    &line[index..=index],
+                    &line[index + 1..],
+                ),
+                None => continue,
+            };
+            let value = substitute(value.trim(), &variables).map_err(parse_error)?;
+
+            if separator == "=" {
+                variables.insert(key.into(), value);
+
+                continue;
+            }
+
+            match key {
+                "Version" => package.version = value,
+                "Requires" => package.requires = Dependency::parse_list(&value)?,
+                "Requires.private" => package.requires_private = Dependency::parse_list(&value)?,
+                "Libs" => package.libs = split_args(&value),
+                "Libs.private" => package.libs_private = split_args(&value),
+                "Cflags" | "CFlags" => package.cflags = split_args(&value),
+                _ => {}
+            }
+        }
+
+        Ok(package)
+    }
+}
+
+/// Substitute `${variable}`s in a value. `$$` is a literal `$`.
+fn substitute(value: &str, variables: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut substituted = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find('$') {
+        substituted.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            substituted.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable in `{}`", value))?;
+            let name = &after[..end];
+            let variable = variables
+                .get(name)
+                .ok_or_else(|| format!("undefined variable `{}`", name))?;
+            substituted.push_str(variable);
+            rest = &after[end + 1..];
+        } else {
+            substituted.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    substituted.push_str(rest);
+
+    Ok(substituted)
+}
+
+/// Split flags on whitespace, keeping quoted and backslash escaped whitespace.
+fn split_args(value: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => arg.extend(chars.next()),
+            ('"', None) | ('\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if !arg.is_empty() {
+                    args.push(std::mem::take(&mut arg))
+                }
+            }
+            (c, _) => arg.push(c),
+        }
+    }
+
+    if !arg.is_empty() {
+        args.push(arg)
+    }
+
+    args
+}
+
+/// Remove duplicates, keeping the last of each so everything still comes before what it needs.
+fn dedup_last(items: Vec<String>) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    let mut deduped: Vec<_> = items
+        .into_iter()
+        .rev()
+        .filter(|item| seen.insert(item.clone()))
+        .collect();
+    deduped.reverse();
+
+    deduped
+}
+
+/// Resolves a package and everything it requires into a [`Library`](Library).
+struct Resolver<'c> {
+    config: &'c Config,
+    visited: BTreeSet<String>,
+    resolving: Vec<String>,
+    library: Library,
+}
+
+impl Resolver<'_> {
+    /// Resolve a package, returning its version. Libraries are only collected if `link` is
+    /// `true`.
+    fn resolve(&mut self, dependency: &Dependency, link: bool) -> Result<String, Error> {
+        let path = self.config.find(&dependency.name)?;
+        let contents = fs::read_to_string(&path).map_err(|error| Error::Io(path.clone(), error))?;
+        let package = Package::parse(&contents, &path, self.config.sysroot.as_deref())?;
+
+        if !dependency.accepts(&package.version) {
+            return Err(Error::Version {
+                dependency: dependency.clone(),
+                found: package.version,
+            });
+        }
+
+        // a package required twice is checked against every constraint, and its libraries are
+        // collected again each time it is linked, so that `dedup_last` keeps them after those of
+        // every package requiring it. Cycles are only followed once.
+        let name = dependency.name.clone();
+        let seen = !self.visited.insert(name.clone());
+
+        if (seen && !link) || self.resolving.contains(&name) {
+            return Ok(package.version);
+        }
+
+        if !self.library.files.contains(&path) {
+            self.library.files.push(path)
+        }
+
+        self.flags(&package.cflags, link, false);
+
+        if link {
+            self.flags(&package.libs, true, true);
+
+            if self.config.statik {
+                self.flags(&package.libs_private, true, true);
+            }
+        }
+
+        self.resolving.push(name);
+
+        for required in &package.requires {
+            self.resolve(required, link)?;
+        }
+
+        for required in &package.requires_private {
+            self.resolve(required, link && self.config.statik)?;
+        }
+
+        self.resolving.pop();
+
+        Ok(package.version)
+    }
+
+    /// Collect the paths and libraries from flags. If `libs` is `true`, the other flags are
+    /// collected as link arguments.
+    fn flags(&mut self, flags: &[String], link: bool, libs: bool) {
+        let config = self.config;
+        let sysrooted = |path: &str| match &config.sysroot {
+            Some(sysroot) if Path::new(path).is_absolute() => {
+                sysroot.join(path.trim_start_matches('/'))
+            }
+            _ => PathBuf::from(path),
+        };
+        let mut flags = flags.iter();
+
+        while let Some(flag) = flags.next() {
+            let (flag, value) = match flag.as_str() {
+                "-L" | "-l" | "-I" | "-framework" => match flags.next() {
+                    Some(value) => (flag.as_str(), value.as_str()),
+                    None => continue,
+                },
+                flag => match (flag.get(..2), flag.get(2..)) {
+                    (Some(name @ "-L"), Some(value))
+                    | (Some(name @ "-l"), Some(value))
+                    | (Some(name @ "-I"), Some(value))
+                        if !value.is_empty() =>
+                    {
+                        (name, value)
+                    }
+                    _ => {
+                        if libs
+                            && flag.starts_with('-')
+                            && !self.library.link_args.iter().any(|arg| arg == flag)
+                        {
+                            self.library.link_args.push(flag.into())
+                        }
+
+                        continue;
+                    }
+                },
+            };
+
+            match flag {
+                "-I" => {
+                    let path = sysrooted(value);
+
+                    if !self.library.include_paths.contains(&path) {
+                        self.library.include_paths.push(path)
+                    }
+                }
+                "-L" if link => {
+                    let path = sysrooted(value);
+
+                    if !self.library.link_paths.contains(&path) {
+                        self.library.link_paths.push(path)
+                    }
+                }
+                "-l" if link => self.library.libs.push(value.into()),
+                "-framework" if link => self.library.libs.push(format!("framework={}", value)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// An error which occurred while resolving a library.
+#[derive(Debug)]
+pub enum Error {
+    /// No `.pc` file was found for the package.
+    NotFound(String),
+
+    /// The version of a package doesn't satisfy a constraint.
+    Version {
+        /// The constraint.
+        dependency: Dependency,
+
+        /// The version found.
+        found: String,
+    },
+
+    /// A version constraint is invalid.
+    Constraint(String),
+
+    /// A `.pc` file is invalid.
+    Parse(PathBuf, String),
+
+    /// A `.pc` file couldn't be read.
+    Io(PathBuf, io::Error),
+
+    /// Cross-compiling, without anywhere to find packages of the target.
+    CrossCompile {
+        /// The host.
+        host: String,
+
+        /// The target.
+        target: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "package `{}` was not found", name),
+            Self::Version { dependency, found } => write!(
+                f,
+                "`{}` is required, but version {} was found",
+                dependency, found
+            ),
+            Self::Constraint(constraint) => {
+                write!(f, "invalid version constraint `{}`", constraint)
+            }
+            Self::Parse(path, message) => {
+                write!(f, "failed to parse {}: {}", path.display(), message)
+            }
+            Self::Io(path, error) => write!(f, "failed to read {}: {}", path.display(), error),
+            Self::CrossCompile { host, target } => write!(
+                f,
+                "cross-compiling from {} to {} without PKG_CONFIG_LIBDIR or \
+                 PKG_CONFIG_SYSROOT_DIR, set PKG_CONFIG_ALLOW_CROSS to search the directories of \
+                 the host",
+                host, target
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_versions, Config, Dependency, Error, Op};
+    use crate::utils::TempDir;
+    use std::cmp::Ordering;
+    use std::path::{Path, PathBuf};
+
+    fn write_pc(dir: &Path, name: &str, contents: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(format!("{}.pc", name)), contents).unwrap()
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.1.1w", "1.1.1"), Ordering::Greater);
+        assert_eq!(compare_versions("3.0.0", "3.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.02"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0a", "1.0b"), Ordering::Less);
+        assert_eq!(compare_versions("1.0.1", "1.0a"), Ordering::Greater)
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let dependencies = Dependency::parse_list("zlib >= 1.2, libcrypto,libssl = 3.0").unwrap();
+        let expected = vec![
+            Dependency {
+                name: "zlib".into(),
+                constraint: Some((Op::Ge, "1.2".into())),
+            },
+            Dependency {
+                name: "libcrypto".into(),
+                constraint: None,
+            },
+            Dependency {
+                name: "libssl".into(),
+                constraint: Some((Op::Eq, "3.0".into())),
+            },
+        ];
+        assert_eq!(dependencies, expected);
+        let dependencies = Dependency::parse_list("zlib>=1.2,libssl!=3.0 libcrypto").unwrap();
+        let expected = vec![
+            Dependency {
+                name: "zlib".into(),
+                constraint: Some((Op::Ge, "1.2".into())),
+            },
+            Dependency {
+                name: "libssl".into(),
+                constraint: Some((Op::Ne, "3.0".into())),
+            },
+            Dependency {
+                name: "libcrypto".into(),
+                constraint: None,
+            },
+        ];
+        assert_eq!(dependencies, expected);
+        assert!(Dependency::parse_list("zlib >=").is_err());
+        assert!(Dependency::parse_list("zlib =>1.2").is_err());
+        assert!(Dependency::parse_list(">= 1.2").is_err())
+    }
+
+    #[test]
+    fn test_probe() {
+        let temp_dir = TempDir::new("pkg_config");
+        let dir = temp_dir.path().to_path_buf();
+        let other = dir.join("other");
+        write_pc(
+            &dir,
+            "libssl",
+            "# comment
+prefix=/opt/ssl
+libdir=${prefix}/lib
+includedir=${prefix}/include
+
+Name: OpenSSL-libssl
+Version: 3.0.13
+Requires: libcrypto >= 3
+Requires.private: zlib
+Libs: -L${libdir} -lssl
+Cflags: -I${includedir}
+",
+        );
+        write_pc(
+            &other,
+            "libcrypto",
+            "prefix=/opt/ssl
+Version: 3.0.13
+Libs: -L${prefix}/lib -lcrypto
+Libs.private: -ldl -pthread
+Cflags: -I${prefix}/include -I\"${pcfiledir}/with space\"
+",
+        );
+        write_pc(
+            &dir,
+            "zlib",
+            "Version: 1.3
+Libs: -L/opt/zlib/lib -lz
+Cflags: -I/opt/zlib/include
+",
+        );
+        let mut config = Config {
+            search_paths: vec![dir.clone(), other.clone()],
+            sysroot: None,
+            statik: false,
+        };
+
+        let library = config.probe("libssl", Some(">=3.0")).unwrap();
+        assert_eq!(library.version, "3.0.13");
+        assert_eq!(library.link_paths, vec![PathBuf::from("/opt/ssl/lib")]);
+        assert_eq!(library.libs, vec!["ssl", "crypto"]);
+        let expected = vec![
+            PathBuf::from("/opt/ssl/include"),
+            other.join("with space"),
+            PathBuf::from("/opt/zlib/include"),
+        ];
+        assert_eq!(library.include_paths, expected);
+        assert_eq!(library.files.len(), 3);
+
+        assert!(library.link_args.is_empty());
+
+        config.statik = true;
+        config.sysroot = Some("/sysroot".into());
+        let library = config.probe("libssl", None).unwrap();
+        assert_eq!(library.libs, vec!["ssl", "crypto", "dl", "z"]);
+        assert_eq!(library.link_args, vec!["-pthread"]);
+        let expected = vec![
+            PathBuf::from("/sysroot/opt/ssl/lib"),
+            PathBuf::from("/sysroot/opt/zlib/lib"),
+        ];
+        assert_eq!(library.link_paths, expected);
+
+        match config.probe("libssl", Some("< 3")) {
+            Err(Error::Version { found, .. }) => assert_eq!(found, "3.0.13"),
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(matches!(
+            config.probe("missing", None),
+            Err(Error::NotFound(_))
+        ));
+        write_pc(&dir, "broken", "Version: ${version}\n");
+        assert!(matches!(
+            config.probe("broken", None),
+            Err(Error::Parse(..))
+        ));
+    }
+
+    #[test]
+    fn test_probe_order() {
+        let dir = TempDir::new("pkg_config_order");
+        write_pc(dir.path(), "a", "Version: 1\nRequires: b, c\nLibs: -la\n");
+        write_pc(dir.path(), "b", "Version: 1\nLibs: -lb xé -l:libb.a\n");
+        write_pc(dir.path(), "c", "Version: 1\nRequires: b, d\nLibs: -lc\n");
+        write_pc(dir.path(), "d", "Version: 1\nRequires: c\nLibs: -ld\n");
+        let config = Config {
+            search_paths: vec![dir.path().into()],
+            sysroot: None,
+            statik: false,
+        };
+
+        // `c` and `d` require each other, which is only followed once.
+        let library = config.probe("a", None).unwrap();
+        assert_eq!(library.libs, vec!["a", "c", "b", ":libb.a", "d"]);
+        assert_eq!(library.files.len(), 4)
+    }
+
+    #[test]
+    fn test_from_env() {
+        fn env<'v>(vars: &'v [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'v {
+            move |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        }
+
+        let config = Config::from_env_in(&env(&[
+            ("TARGET", "x86_64-unknown-linux-gnu"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("PKG_CONFIG_PATH", "/opt/pc"),
+        ]))
+        .unwrap();
+        assert_eq!(config.search_paths[0], PathBuf::from("/opt/pc"));
+        assert_eq!(
+            config.search_paths[1],
+            PathBuf::from("/usr/lib/x86_64-linux-gnu/pkgconfig")
+        );
+
+        let cross = [
+            ("TARGET", "aarch64-linux-android"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("PKG_CONFIG_PATH", "/opt/pc"),
+            ("PKG_CONFIG_LIBDIR_aarch64_linux_android", "/ndk/pc"),
+        ];
+        assert!(matches!(
+            Config::from_env_in(&env(&cross[..3])),
+            Err(Error::CrossCompile { .. })
+        ));
+        let config = Config::from_env_in(&env(&cross)).unwrap();
+        let expected = vec![PathBuf::from("/opt/pc"), PathBuf::from("/ndk/pc")];
+        assert_eq!(config.search_paths, expected);
+        let config = Config::from_env_in(&env(&[
+            ("TARGET", "aarch64-linux-android"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("PKG_CONFIG_ALLOW_CROSS", "1"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            config.search_paths[0],
+            PathBuf::from("/usr/lib/aarch64-linux-android/pkgconfig")
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Policies applied to the instruction stack when [`build()`](crate::BuildScript::build) is called.
+//! # Notes
+//! In [`now`](crate::BuildScript::now) mode, instructions are written as soon as they are passed,
+//! so these policies have no effect.
+use crate::{Instruction, Prefix};
+use std::collections::HashSet;
+
+/// How duplicate instructions are handled.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Dedup {
+    /// Keep every instruction. This is the default.
+    KeepAll,
+
+    /// Keep only the first of identical instructions, unless their order matters (see
+    /// [`Instruction::is_order_sensitive()`](Instruction::is_order_sensitive)), in which case
+    /// every one of them is kept. For example, a library linked twice is kept twice, but a path
+    /// tracked twice through `rerun-if-changed` is only kept once.
+    KeepFirst,
+}
+
+impl Default for Dedup {
+    /// The default is [`KeepAll`](Self::KeepAll).
+    fn default() -> Self {
+        Self::KeepAll
+    }
+}
+
+/// The order instructions are written in.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Order {
+    /// The order the instructions were passed in. This is the default.
+    CallOrder,
+
+    /// Group the instructions: `rerun-if-*` first, then `rustc-cfg` and `rustc-check-cfg`, then
+    /// `rustc-env`, then the ones affecting linking, then everything else. Within a group, the
+    /// order the instructions were passed in is kept.
+    Canonical,
+}
+
+impl Default for Order {
+    /// The default is [`CallOrder`](Self::CallOrder).
+    fn default() -> Self {
+        Self::CallOrder
+    }
+}
+
+/// The group of an instruction in the [`Canonical`](Order::Canonical) order.
+fn group(instruction: &Instruction) -> u8 {
+    let name = match (&instruction.prefix, &instruction.name) {
+        (Prefix::Cargo, Some(name)) => name.as_str(),
+        _ => return 4,
+    };
+
+    match name {
+        "rerun-if-changed" | "rerun-if-env-changed" => 0,
+        "rustc-cfg" | "rustc-check-cfg" => 1,
+        "rustc-env" => 2,
+        _ if instruction.is_order_sensitive() => 3,
+        _ => 4,
+    }
+}
+
+/// Apply the policies to a list of items holding instructions, such as the instruction stack.
+pub(crate) fn apply<T>(
+    items: Vec<T>,
+    instruction: impl Fn(&T) -> &Instruction,
+    dedup: Dedup,
+    order: Order,
+) -> Vec<T> {
+    let mut items = match dedup {
+        Dedup::KeepAll => items,
+        Dedup::KeepFirst => {
+            let mut seen = HashSet::new();
+            let keep: Vec<bool> = items
+                .iter()
+                .map(|item| {
+                    let instruction = instruction(item);
+
+                    instruction.is_order_sensitive() || seen.insert(instruction.clone())
+                })
+                .collect();
+
+            items
+                .into_iter()
+                .zip(keep)
+                .filter(|(_, keep)| *keep)
+                .map(|(item, _)| item)
+                .collect()
+        }
+    };
+
+    if let Order::Canonical = order {
+        // sorting is stable, so the order within a group is kept.
+        items.sort_by_key(|item| group(instruction(item)))
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dedup, Order};
+    use crate::{Instruction, Value};
+
+    fn instructions() -> Vec<Instruction> {
+        vec![
+            Instruction::new("warning", Value::Singular("hello".into())),
+            Instruction::new(
+                "rustc-link-lib",
+                Value::UnquotedOptionalKey(None, "ssl".into()),
+            ),
+            Instruction::new("rerun-if-changed", Value::Singular("build.rs".into())),
+            Instruction::new("rustc-cfg", Value::OptionalValue("ossl".into(), None)),
+            Instruction::new(
+                "rustc-link-lib",
+                Value::UnquotedOptionalKey(None, "ssl".into()),
+            ),
+            Instruction::new("rerun-if-changed", Value::Singular("build.rs".into())),
+            Instruction::new(
+                "rustc-env",
+                Value::UnquotedMapping("VERSION".into(), "1".into()),
+            ),
+            Instruction::new("rerun-if-env-changed", Value::Singular("CC".into())),
+        ]
+    }
+
+    fn apply(dedup: Dedup, order: Order) -> Vec<String> {
+        super::apply(instructions(), |instruction| instruction, dedup, order)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_default() {
+        let expected: Vec<String> = instructions().iter().map(ToString::to_string).collect();
+        assert_eq!(apply(Dedup::default(), Order::default()), expected)
+    }
+
+    #[test]
+    fn test_keep_first() {
+        let expected = vec![
+            "cargo:warning=hello",
+            "cargo:rustc-link-lib=ssl",
+            "cargo:rerun-if-changed=build.rs",
+            "cargo:rustc-cfg=ossl",
+            "cargo:rustc-link-lib=ssl",
+            "cargo:rustc-env=VERSION=1",
+            "cargo:rerun-if-env-changed=CC",
+        ];
+        assert_eq!(apply(Dedup::KeepFirst, Order::CallOrder), expected)
+    }
+
+    #[test]
+    fn test_canonical() {
+        let expected = vec![
+            "cargo:rerun-if-changed=build.rs",
+            "cargo:rerun-if-env-changed=CC",
+            "cargo:rustc-cfg=ossl",
+            "cargo:rustc-env=VERSION=1",
+            "cargo:rustc-link-lib=ssl",
+            "cargo:rustc-link-lib=ssl",
+            "cargo:warning=hello",
+        ];
+        assert_eq!(apply(Dedup::KeepFirst, Order::Canonical), expected)
+    }
+}
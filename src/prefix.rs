@@ -29,6 +29,29 @@ impl fmt::Display for Prefix {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Prefix {
+    /// Serialized as the prefix itself, such as `"cargo"`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Prefix {
+    /// `"cargo"` is deserialized as [`Cargo`](Self::Cargo), everything else as
+    /// [`Custom`](Self::Custom).
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let prefix = String::deserialize(deserializer)?;
+
+        if prefix == "cargo" {
+            Ok(Self::Cargo)
+        } else {
+            Ok(Self::Custom(prefix))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::Prefix;
@@ -52,4 +75,15 @@ mod tests {
         let string = format!("{}", prefix);
         assert_eq!(string, "custom")
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let json = serde_json::to_string(&Prefix::Cargo).unwrap();
+        assert_eq!(json, "\"cargo\"");
+        let prefix: Prefix = serde_json::from_str("\"cargo\"").unwrap();
+        assert_eq!(prefix, Prefix::Cargo);
+        let prefix: Prefix = serde_json::from_str("\"custom\"").unwrap();
+        assert_eq!(prefix, Prefix::Custom("custom".into()))
+    }
 }
@@ -0,0 +1,278 @@
+//! Checking whether Rust code compiles for the target, like `autocfg`. See
+//! [`BuildScript::probe_code()`](crate::BuildScript::probe_code).
+//! # Examples
+//! ```rust,no_run
+//! use build_script::BuildScript;
+//!
+//! let mut build_script = BuildScript::default();
+//! build_script.probe_expression("has_black_box", "std::hint::black_box(1)");
+//! build_script.probe_type("has_i128", "i128");
+//! build_script.probe_path("has_once_lock", "std::sync::OnceLock");
+//! build_script.probe_trait("has_error_in_core", "core::error::Error");
+//! build_script.build();
+//! ```
+use crate::rustc::Rustc;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, io};
+
+/// How code is compiled for the target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Probe {
+    /// The compiler.
+    pub rustc: Rustc,
+
+    /// The target triple, passed as `--target` if set.
+    pub target: Option<String>,
+
+    /// The flags passed to the compiler, such as those from `RUSTFLAGS`.
+    pub rustflags: Vec<String>,
+
+    /// Where scratch directories are created. They are removed after each probe.
+    pub out_dir: PathBuf,
+}
+
+impl Probe {
+    /// Compile like cargo does for the package: the compiler from
+    /// [`Rustc::from_env()`](Rustc::from_env), `TARGET`, the flags from
+    /// `CARGO_ENCODED_RUSTFLAGS` and scratch directories in `OUT_DIR`.
+    /// # Panics
+    /// This panics if `OUT_DIR` isn't set, which cargo always sets for build scripts.
+    pub fn from_env() -> Self {
+        let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is not set");
+        let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS")
+            .map(|flags| {
+                flags
+                    .split('\x1f')
+                    .filter(|flag| !flag.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            rustc: Rustc::from_env(),
+            target: env::var("TARGET").ok(),
+            rustflags,
+            out_dir: out_dir.into(),
+        }
+    }
+
+    /// Returns `true` if `code` compiles as a library crate.
+    pub fn compiles(&self, code: &str) -> io::Result<bool> {
+        let output = self.compile(code, Command::output)?;
+
+        Ok(output.status.success())
+    }
+
+    /// Compile `code` as a library crate in a scratch directory, with `run` running the compiler.
+    /// The scratch directory is removed afterwards.
+    pub(crate) fn compile(
+        &self,
+        code: &str,
+        run: impl FnOnce(&mut Command) -> io::Result<Output>,
+    ) -> io::Result<Output> {
+        /// Makes the scratch directories of concurrent probes unique.
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let scratch = self.out_dir.join(format!(
+            "build-script-probe-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&scratch)?;
+
+        let result = (|| {
+            let source = scratch.join("probe.rs");
+            fs::write(&source, code)?;
+            let mut command = self.rustc.command();
+            command
+                .args(["--crate-name", "build_script_probe", "--crate-type", "lib"])
+                .args(["--emit", "metadata", "--cap-lints", "allow", "--out-dir"])
+                .arg(&scratch);
+
+            if let Some(target) = &self.target {
+                command.args(["--target", target]);
+            }
+
+            command.args(&self.rustflags).arg(&source);
+
+            run(&mut command)
+        })();
+        let removed = fs::remove_dir_all(&scratch);
+
+        // a failed probe is more useful to report than a failed cleanup.
+        let output = result?;
+        removed?;
+
+        Ok(output)
+    }
+}
+
+/// The code probing an expression.
+pub(crate) fn expression(expr: &str) -> String {
+    format!("pub fn probe() {{ let _ = {}; }}", expr)
+}
+
+/// The code probing a type.
+pub(crate) fn ty(ty: &str) -> String {
+    format!("pub type Probe = {};", ty)
+}
+
+/// The code probing a path.
+pub(crate) fn path(path: &str) -> String {
+    format!("pub use {};", path)
+}
+
+/// The code probing a trait.
+pub(crate) fn trait_(name: &str) -> String {
+    format!("pub trait Probe: {} + Sized {{}}", name)
+}
+
+/// The code probing a feature gate.
+pub(crate) fn feature(gate: &str) -> String {
+    format!("#![feature({})]", gate)
+}
+
+/// What to set `RUSTC_BOOTSTRAP` to when probing a feature gate for the crate `crate_name`, given
+/// its value for the build script. Returns [`None`](None) if it should be unset.
+/// # Notes
+/// The probe is compiled as a different crate, so a `RUSTC_BOOTSTRAP` naming the crate is passed
+/// as `1`.
+pub(crate) fn bootstrap(value: Option<&str>, crate_name: &str) -> Option<&'static str> {
+    match value? {
+        "1" => Some("1"),
+        "-1" => Some("-1"),
+        crates if crates.split(',').any(|name| name.trim() == crate_name) => Some("1"),
+        _ => None,
+    }
+}
+
+/// The file in `OUT_DIR` listing the feature gates which compiled the last time they were probed.
+const FEATURE_STATE: &str = "build-script-feature-gates";
+
+/// Returns `true` if `gate` compiled the last time it was probed with this `OUT_DIR`.
+pub(crate) fn worked_before(out_dir: &Path, gate: &str) -> bool {
+    fs::read_to_string(out_dir.join(FEATURE_STATE))
+        .map(|state| state.lines().any(|line| line == gate))
+        .unwrap_or(false)
+}
+
+/// Record whether or not `gate` compiled.
+pub(crate) fn remember(out_dir: &Path, gate: &str, works: bool) -> io::Result<()> {
+    let path = out_dir.join(FEATURE_STATE);
+    let state = fs::read_to_string(&path).unwrap_or_default();
+    let mut gates: Vec<_> = state.lines().filter(|line| *line != gate).collect();
+
+    if works {
+        gates.push(gate)
+    }
+
+    fs::create_dir_all(out_dir)?;
+    fs::write(path, gates.join("\n"))
+}
+
+/// The first error in the stderr of the compiler, such as
+/// `error[E0557]: feature has been removed`.
+pub(crate) fn first_error(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .find(|line| line.starts_with("error"))
+        .unwrap_or("unknown error")
+        .to_string()
+}
+
+/// The `stable_features` warning of the compiler for `gate`, such as
+/// ``warning: the feature `i128_type` has been stable since 1.26.0 [...]``, if it was stabilized.
+/// # Notes
+/// Every other lint is capped, so the only warning mentioning the gate is this one.
+pub(crate) fn stabilized(stderr: &[u8], gate: &str) -> Option<String> {
+    let gate = format!("`{}`", gate);
+
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .find(|line| line.starts_with("warning") && line.contains(&gate))
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Probe;
+    use crate::rustc::Rustc;
+    use crate::utils::TempDir;
+
+    fn probe(out_dir: &TempDir) -> Probe {
+        Probe {
+            rustc: Rustc::new("rustc"),
+            target: None,
+            rustflags: Vec::new(),
+            out_dir: out_dir.path().into(),
+        }
+    }
+
+    #[test]
+    fn test_compiles() {
+        let out_dir = TempDir::new("probe_compiles");
+        let probe = probe(&out_dir);
+        assert!(probe.compiles(&super::expression("1 + 1")).unwrap());
+        assert!(probe.compiles(&super::ty("u128")).unwrap());
+        assert!(probe
+            .compiles(&super::path("std::collections::HashMap"))
+            .unwrap());
+        assert!(probe.compiles(&super::trait_("std::fmt::Debug")).unwrap());
+        assert!(!probe.compiles(&super::path("std::not_a_module")).unwrap());
+        assert!(!probe.compiles("not rust").unwrap());
+        let leftover = std::fs::read_dir(&probe.out_dir).unwrap().count();
+        assert_eq!(leftover, 0)
+    }
+
+    #[test]
+    fn test_rustflags() {
+        let out_dir = TempDir::new("probe_rustflags");
+        let mut probe = probe(&out_dir);
+        let code = "#[cfg(probe_flag)] pub fn probe() {} pub use self::probe as used;";
+        assert!(!probe.compiles(code).unwrap());
+        probe.rustflags = vec!["--cfg".into(), "probe_flag".into()];
+        assert!(probe.compiles(code).unwrap())
+    }
+
+    #[test]
+    fn test_bootstrap() {
+        assert_eq!(super::bootstrap(None, "my_crate"), None);
+        assert_eq!(super::bootstrap(Some("1"), "my_crate"), Some("1"));
+        assert_eq!(super::bootstrap(Some("-1"), "my_crate"), Some("-1"));
+        assert_eq!(
+            super::bootstrap(Some("other,my_crate"), "my_crate"),
+            Some("1")
+        );
+        assert_eq!(super::bootstrap(Some("other"), "my_crate"), None);
+        assert_eq!(super::bootstrap(Some("0"), "my_crate"), None)
+    }
+
+    #[test]
+    fn test_stabilized() {
+        let stderr =
+            b"warning: the feature `i128_type` has been stable since 1.26.0 and no longer \
+requires an attribute to enable\n --> probe.rs:1:12\n\nwarning: 1 warning emitted\n";
+        let warning = super::stabilized(stderr, "i128_type").unwrap();
+        assert!(warning.ends_with(
+            "has been stable since 1.26.0 and no longer requires an attribute to enable"
+        ));
+        assert_eq!(super::stabilized(stderr, "i128"), None);
+        assert_eq!(super::stabilized(b"", "i128_type"), None)
+    }
+
+    #[test]
+    fn test_feature_state() {
+        let out_dir = TempDir::new("feature_state");
+        let out_dir = out_dir.path();
+        assert!(!super::worked_before(out_dir, "portable_simd"));
+        super::remember(out_dir, "portable_simd", true).unwrap();
+        super::remember(out_dir, "never_type", true).unwrap();
+        assert!(super::worked_before(out_dir, "portable_simd"));
+        super::remember(out_dir, "portable_simd", false).unwrap();
+        assert!(!super::worked_before(out_dir, "portable_simd"));
+        assert!(super::worked_before(out_dir, "never_type"))
+    }
+}
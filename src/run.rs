@@ -0,0 +1,275 @@
+//! This contains the [`run()`](run) entry point.
+use crate::BuildScript;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::Once;
+
+/// The exit code when the build script returned an error.
+const ERROR_CODE: i32 = 1;
+
+/// The exit code when the build script panicked, the same as an uncaught panic.
+const PANIC_CODE: i32 = 101;
+
+thread_local! {
+    /// Whether or not [`run()`](run) is running on this thread.
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+
+    /// The report of the last panic caught on this thread.
+    static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Install the panic hook. Panics inside of [`run()`](run) are recorded instead of printed,
+/// everything else is passed to the previous hook.
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !RUNNING.with(Cell::get) {
+                return previous(info);
+            }
+
+            let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+                message
+            } else if let Some(message) = info.payload().downcast_ref::<String>() {
+                message.as_str()
+            } else {
+                "Box<dyn Any>"
+            };
+            let report = match info.location() {
+                Some(location) => format!("panicked at {}: {}", location, message),
+                None => format!("panicked: {}", message),
+            };
+
+            PANIC.with(|panic| *panic.borrow_mut() = Some(report))
+        }))
+    })
+}
+
+/// The entry point of a build script. This is meant to be the whole body of `main` in
+/// `build.rs`.
+///
+/// `func` is called with a [`BuildScript`](BuildScript) writing to stdout, and all the queued
+/// instructions are written once it returns. If it returns an error, the queued instructions are
+/// still written, then the error and each of its sources are reported through
+/// [`cargo_error()`](BuildScript::cargo_error), one per line, and the process exits with a
+/// non-zero code. This is `cargo::error` with [`CargoModern`](crate::output::CargoModern), or
+/// `cargo:warning` with the default [`CargoLegacy`](crate::output::CargoLegacy), which predates
+/// it. If it panics, the queued instructions are discarded, the panic message and its location are
+/// reported the same way instead of a backtrace and the process exits with the same code as an
+/// uncaught panic.
+/// # Examples
+/// ```rust,no_run
+/// build_script::run(|build_script| -> Result<(), std::io::Error> {
+///     build_script.cargo_rerun_if_changed("wrapper.h".into());
+///     std::fs::metadata("wrapper.h")?;
+///
+///     Ok(())
+/// })
+/// ```
+pub fn run<E: Into<Box<dyn Error>>>(func: impl FnOnce(&mut BuildScript) -> Result<(), E>) {
+    let code = run_with(&mut BuildScript::default(), func);
+
+    if code != 0 {
+        process::exit(code)
+    }
+}
+
+/// [`run()`](run), but with the specified build script. Returns the exit code instead of exiting.
+fn run_with<'w, E: Into<Box<dyn Error>>>(
+    build_script: &mut BuildScript<'w>,
+    func: impl FnOnce(&mut BuildScript<'w>) -> Result<(), E>,
+) -> i32 {
+    install_hook();
+
+    let was_running = RUNNING.with(|running| running.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| func(build_script)));
+    RUNNING.with(|running| running.set(was_running));
+
+    let (mut messages, code) = match result {
+        Ok(Ok(())) => (Vec::new(), 0),
+        Ok(Err(error)) => {
+            let error = error.into();
+            let mut messages = vec![error.to_string()];
+            let mut source = error.source();
+
+            while let Some(error) = source {
+                messages.push(format!("caused by: {}", error));
+                source = error.source()
+            }
+
+            (messages, ERROR_CODE)
+        }
+        Err(_) => {
+            build_script.take_pending();
+            let report = PANIC.with(|panic| panic.borrow_mut().take());
+
+            (
+                vec![report.unwrap_or_else(|| "panicked".into())],
+                PANIC_CODE,
+            )
+        }
+    };
+
+    if let Err(error) = build_script.try_build() {
+        // the error already reports the rejections, which would fail the next build again.
+        build_script.take_pending();
+        build_script.take_rejections();
+        messages.push(error.to_string());
+    }
+
+    for message in &messages {
+        // conflicts and rejections are reported one per line as well.
+        for line in message.lines() {
+            build_script.cargo_error(line);
+        }
+    }
+
+    if build_script.try_build().is_err() {
+        // an interceptor rejected the reports themselves, there is nothing left to report with.
+        build_script.take_pending();
+        build_script.take_rejections();
+    }
+
+    match (code, messages.is_empty()) {
+        (0, false) => ERROR_CODE,
+        (code, _) => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_with;
+    use crate::output::CargoModern;
+    use crate::BuildScript;
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct Outer(std::io::Error);
+
+    impl fmt::Display for Outer {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "failed to find library")
+        }
+    }
+
+    impl Error for Outer {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn lines(writer: &[u8]) -> Vec<String> {
+        String::from_utf8_lossy(writer)
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_ok() {
+        let mut writer = Vec::new();
+        let code = run_with(&mut BuildScript::new(&mut writer), |build_script| {
+            build_script.cargo_rustc_link_lib(None, "ssl");
+
+            Ok::<_, Outer>(())
+        });
+        assert_eq!(code, 0);
+        assert_eq!(lines(&writer), vec!["cargo:rustc-link-lib=ssl"])
+    }
+
+    #[test]
+    fn test_error() {
+        let mut writer = Vec::new();
+        let code = run_with(&mut BuildScript::new(&mut writer), |build_script| {
+            build_script.cargo_rustc_link_lib(None, "ssl");
+            let error = std::io::Error::new(std::io::ErrorKind::NotFound, "libssl.so");
+
+            Err(Outer(error))
+        });
+        assert_eq!(code, 1);
+        let expected = vec![
+            "cargo:rustc-link-lib=ssl",
+            "cargo:warning=error: failed to find library",
+            "cargo:warning=error: caused by: libssl.so",
+        ];
+        assert_eq!(lines(&writer), expected)
+    }
+
+    #[test]
+    fn test_error_modern() {
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.output_format(CargoModern);
+        let code = run_with(&mut build_script, |_| {
+            let error = std::io::Error::new(std::io::ErrorKind::NotFound, "libssl.so");
+
+            Err(Outer(error))
+        });
+        assert_eq!(code, 1);
+        drop(build_script);
+        let expected = vec![
+            "cargo::error=failed to find library",
+            "cargo::error=caused by: libssl.so",
+        ];
+        assert_eq!(lines(&writer), expected)
+    }
+
+    #[test]
+    fn test_rejected() {
+        use crate::intercept::Action;
+        use crate::Instruction;
+
+        let mut writer = Vec::new();
+        let mut build_script = BuildScript::new(&mut writer);
+        build_script.intercept(
+            |instruction: Instruction| match instruction.name.as_deref() {
+                Some("rustc-flags") => Action::Reject("rustc-flags is forbidden".into()),
+                _ => Action::Pass(instruction),
+            },
+        );
+        let line = line!() + 2;
+        let code = run_with(&mut build_script, |build_script| {
+            build_script.cargo_rustc_flags("-l ssl");
+
+            Ok::<_, Outer>(())
+        });
+        assert_eq!(code, 1);
+        assert!(build_script.try_build().is_ok());
+        drop(build_script);
+        let expected = vec![
+            "cargo:warning=error: 1 instruction(s) rejected".to_string(),
+            format!(
+                "cargo:warning=error: `cargo:rustc-flags=-l ssl` (at {}:{}:26) was rejected: \
+                 rustc-flags is forbidden",
+                file!(),
+                line
+            ),
+        ];
+        assert_eq!(lines(&writer), expected)
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut writer = Vec::new();
+        let line = line!() + 3;
+        let code = run_with(&mut BuildScript::new(&mut writer), |build_script| {
+            build_script.cargo_rustc_link_lib(None, "ssl");
+            panic!("no {}", "openssl");
+            #[allow(unreachable_code)]
+            Ok::<_, Outer>(())
+        });
+        assert_eq!(code, 101);
+        let expected = format!(
+            "cargo:warning=error: panicked at {}:{}:13: no openssl",
+            file!(),
+            line
+        );
+        assert_eq!(lines(&writer), vec![expected])
+    }
+}
@@ -0,0 +1,320 @@
+//! Probing the compiler cargo builds the package with.
+//! # Examples
+//! ```rust,no_run
+//! use build_script::rustc::Rustc;
+//! use build_script::BuildScript;
+//!
+//! let version = Rustc::from_env().version().unwrap();
+//! let mut build_script = BuildScript::default();
+//!
+//! // `rustc_1_70` and `rustc_1_80` if the compiler is at least that new, and `nightly`.
+//! build_script.rustc_version_cfgs(&version, &[70, 80]);
+//! build_script.build();
+//! ```
+use std::path::PathBuf;
+use std::process::Command;
+use std::{env, fmt, io};
+
+/// A compiler, and the wrappers it is run through.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rustc {
+    /// The compiler.
+    pub program: PathBuf,
+
+    /// The wrappers, outermost first. Each one is passed the next one (or the compiler) as its
+    /// first argument.
+    pub wrappers: Vec<PathBuf>,
+}
+
+impl Rustc {
+    /// A compiler without any wrappers.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            wrappers: Vec::new(),
+        }
+    }
+
+    /// The compiler cargo builds the package with. This is `RUSTC` (or `rustc` if it isn't set),
+    /// run through `RUSTC_WRAPPER` and `RUSTC_WORKSPACE_WRAPPER` like cargo does, if they are set
+    /// and not empty.
+    pub fn from_env() -> Self {
+        let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+        let program = var("RUSTC").unwrap_or_else(|| "rustc".into());
+        let wrappers = ["RUSTC_WRAPPER", "RUSTC_WORKSPACE_WRAPPER"]
+            .iter()
+            .filter_map(|name| var(name))
+            .map(PathBuf::from)
+            .collect();
+
+        Self {
+            program: program.into(),
+            wrappers,
+        }
+    }
+
+    /// A [`Command`](Command) running the compiler through its wrappers, without any arguments
+    /// to the compiler yet.
+    pub fn command(&self) -> Command {
+        let mut programs = self.wrappers.iter().chain(Some(&self.program));
+        let mut command = Command::new(programs.next().expect("there is always a compiler"));
+        command.args(programs);
+
+        command
+    }
+
+    /// Run the compiler with `-vV` and parse its version.
+    pub fn version(&self) -> Result<RustcVersion, Error> {
+        let output = self.command().arg("-vV").output().map_err(Error::Io)?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+            return Err(Error::Failed(stderr));
+        }
+
+        RustcVersion::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+/// The release channel of a compiler.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Channel {
+    /// A stable release.
+    Stable,
+
+    /// A beta release, such as `1.80.0-beta.3`.
+    Beta,
+
+    /// A nightly release, such as `1.80.0-nightly`.
+    Nightly,
+
+    /// A compiler built from source, such as `1.80.0-dev`.
+    Dev,
+}
+
+/// A release version, such as `1.80.0`.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Version {
+    /// The major version.
+    pub major: u32,
+
+    /// The minor version.
+    pub minor: u32,
+
+    /// The patch version.
+    pub patch: u32,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The version of a compiler, as printed by `rustc -vV`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RustcVersion {
+    /// The release version, without the channel.
+    pub version: Version,
+
+    /// The release channel.
+    pub channel: Channel,
+
+    /// The commit the compiler was built from, if known.
+    pub commit_hash: Option<String>,
+
+    /// The date of the commit the compiler was built from, if known.
+    pub commit_date: Option<String>,
+
+    /// The version of LLVM the compiler uses, if it uses LLVM.
+    pub llvm_version: Option<String>,
+
+    /// The target triple of the host.
+    pub host: String,
+}
+
+impl RustcVersion {
+    /// Parse the output of `rustc -vV`.
+    pub fn parse(output: &str) -> Result<Self, Error> {
+        let field = |name: &str| {
+            output.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+
+                if key.trim() == name {
+                    Some(value.trim().to_string())
+                } else {
+                    None
+                }
+            })
+        };
+        let known = |value: Option<String>| value.filter(|value| value != "unknown");
+        let release = field("release").ok_or_else(|| Error::Parse("missing release".into()))?;
+        let (version, pre) = match release.split_once('-') {
+            Some((version, pre)) => (version, Some(pre)),
+            None => (release.as_str(), None),
+        };
+        let channel = match pre {
+            None => Channel::Stable,
+            Some(pre) if pre.starts_with("beta") => Channel::Beta,
+            Some(pre) if pre.starts_with("nightly") => Channel::Nightly,
+            Some(pre) if pre.starts_with("dev") => Channel::Dev,
+            Some(pre) => return Err(Error::Parse(format!("unknown channel `{}`", pre))),
+        };
+        let mut numbers = version.split('.').map(str::parse::<u32>);
+        let mut number = || match numbers.next() {
+            Some(Ok(number)) => Ok(number),
+            _ => Err(Error::Parse(format!("invalid release `{}`", release))),
+        };
+        let version = Version {
+            major: number()?,
+            minor: number()?,
+            patch: number()?,
+        };
+
+        Ok(Self {
+            version,
+            channel,
+            commit_hash: known(field("commit-hash")),
+            commit_date: known(field("commit-date")),
+            llvm_version: field("LLVM version"),
+            host: field("host").ok_or_else(|| Error::Parse("missing host".into()))?,
+        })
+    }
+
+    /// Returns `true` if this is at least version `major.minor`.
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        (self.version.major, self.version.minor) >= (major, minor)
+    }
+
+    /// Returns `true` if unstable features can be used without `RUSTC_BOOTSTRAP`, which is the
+    /// case for nightly and dev compilers.
+    pub fn is_nightly(&self) -> bool {
+        matches!(self.channel, Channel::Nightly | Channel::Dev)
+    }
+}
+
+/// An error which occurred while probing the compiler.
+#[derive(Debug)]
+pub enum Error {
+    /// The compiler couldn't be run.
+    Io(io::Error),
+
+    /// The compiler failed, with this stderr.
+    Failed(String),
+
+    /// The output of the compiler couldn't be parsed.
+    Parse(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to run rustc: {}", error),
+            Self::Failed(stderr) => write!(f, "rustc failed: {}", stderr),
+            Self::Parse(message) => write!(f, "failed to parse the rustc version: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{Channel, Rustc, RustcVersion, Version};
+    #[cfg(unix)]
+    use crate::utils::TempDir;
+    #[cfg(unix)]
+    use std::path::Path;
+
+    const NIGHTLY: &str = "rustc 1.82.0-nightly (6de928dce 2024-08-18)
+binary: rustc
+commit-hash: 6de928dce9472b864f4e5d590dd7aa2075cb7551
+commit-date: 2024-08-18
+host: x86_64-unknown-linux-gnu
+release: 1.82.0-nightly
+LLVM version: 19.1.0
+";
+
+    /// Write a fake compiler to `dir`, which prints `stdout` for `-vV` and runs `script` otherwise.
+    /// # Notes
+    /// The script is run by `sh` as a wrapper instead of being executed itself, since executing a
+    /// file just written fails with `ETXTBSY` if another test forks while it is still open.
+    #[cfg(unix)]
+    pub(crate) fn fake_rustc(dir: &Path, stdout: &str, script: &str) -> Rustc {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("rustc");
+        let contents = format!(
+            "if [ \"$1\" = \"-vV\" ]; then\ncat <<'EOF'\n{}EOF\nexit 0\nfi\n{}\n",
+            stdout, script
+        );
+        std::fs::write(&path, contents).unwrap();
+
+        Rustc {
+            program: path,
+            wrappers: vec!["sh".into()],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let version = RustcVersion::parse(NIGHTLY).unwrap();
+        let expected = RustcVersion {
+            version: Version {
+                major: 1,
+                minor: 82,
+                patch: 0,
+            },
+            channel: Channel::Nightly,
+            commit_hash: Some("6de928dce9472b864f4e5d590dd7aa2075cb7551".into()),
+            commit_date: Some("2024-08-18".into()),
+            llvm_version: Some("19.1.0".into()),
+            host: "x86_64-unknown-linux-gnu".into(),
+        };
+        assert_eq!(version, expected);
+        assert!(version.at_least(1, 80));
+        assert!(!version.at_least(1, 83));
+        assert!(version.is_nightly());
+
+        let stable = "release: 1.80.1\ncommit-hash: unknown\nhost: aarch64-apple-darwin\n";
+        let stable = RustcVersion::parse(stable).unwrap();
+        assert_eq!(stable.channel, Channel::Stable);
+        assert_eq!(stable.commit_hash, None);
+        assert_eq!(stable.version.to_string(), "1.80.1");
+        assert!(RustcVersion::parse("release: 1.80\nhost: x\n").is_err());
+        assert!(RustcVersion::parse("host: x\n").is_err())
+    }
+
+    #[test]
+    fn test_command() {
+        let rustc = Rustc {
+            program: "rustc".into(),
+            wrappers: vec!["sccache".into(), "clippy-driver".into()],
+        };
+        let command = rustc.command();
+        assert_eq!(command.get_program(), "sccache");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, vec!["clippy-driver", "rustc"])
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_version() {
+        let dir = TempDir::new("rustc_version");
+        let rustc = fake_rustc(dir.path(), NIGHTLY, "echo failed >&2; exit 1");
+        assert_eq!(rustc.version().unwrap().version.minor, 82);
+        // the wrapper is passed the compiler first, so it fails like the compiler would.
+        let wrapped = Rustc {
+            program: "rustc".into(),
+            wrappers: vec!["sh".into(), rustc.program],
+        };
+        assert!(matches!(wrapped.version(), Err(super::Error::Failed(_))))
+    }
+}
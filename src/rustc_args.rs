@@ -0,0 +1,249 @@
+//! Compute the `rustc` arguments cargo derives from a set of [`Instruction`](Instruction)s.
+use crate::rustc_flags::{self, Flag};
+use crate::{Instruction, Prefix};
+use std::collections::BTreeMap;
+
+/// The kind of target being compiled. Cargo only applies some instructions to some targets.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum TargetKind {
+    /// A library target (`lib`, `rlib`, `dylib`, `staticlib`...).
+    Lib,
+
+    /// A `cdylib` library target.
+    Cdylib,
+
+    /// A binary target with the specified name.
+    Bin(String),
+
+    /// A test target.
+    Test,
+
+    /// A benchmark target.
+    Bench,
+
+    /// An example target.
+    Example,
+}
+
+impl TargetKind {
+    /// Returns `true` if the target is a library.
+    pub fn is_lib(&self) -> bool {
+        matches!(*self, Self::Lib | Self::Cdylib)
+    }
+}
+
+/// The arguments and environment passed to `rustc` because of a build script.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RustcArgs {
+    /// The command line arguments, in the order of their instructions.
+    pub args: Vec<String>,
+
+    /// The environment variables set for the compiler (available through `env!`).
+    pub env: BTreeMap<String, String>,
+}
+
+impl RustcArgs {
+    /// Push a flag and its value.
+    fn push(&mut self, flag: &str, value: impl Into<String>) {
+        self.args.push(flag.into());
+        self.args.push(value.into())
+    }
+
+    /// Push a `-C link-arg=FLAG` argument.
+    fn push_link_arg(&mut self, flag: &str) {
+        self.push("-C", format!("link-arg={}", flag))
+    }
+}
+
+/// The value of a `-l` or `-L` flag.
+fn lib_or_path(flag: &Flag) -> String {
+    flag.instruction().value.to_string()
+}
+
+/// Compute the arguments passed to `rustc` for a target of `kind` because of `instructions`.
+/// `has_library` is whether the package has a library target at all.
+/// # Notes
+/// Cargo's rules are followed: libraries (`-l`) are only linked into the library target, unless
+/// the package doesn't have one, in which case they are linked into every target. Link arguments
+/// (`-C link-arg`) only apply to their respective targets, while search paths, `cfg`s and
+/// environment variables apply to every target. Instructions which don't affect the compiler, such
+/// as `rerun-if-changed` or metadata, are ignored.
+/// # Errors
+/// Like cargo, this fails if a `rustc-flags` instruction contains a flag which can't be parsed.
+pub fn rustc_args(
+    instructions: &[Instruction],
+    kind: &TargetKind,
+    has_library: bool,
+) -> Result<RustcArgs, rustc_flags::Error> {
+    let mut args = RustcArgs::default();
+    let links_libraries = kind.is_lib() || !has_library;
+
+    for instruction in instructions {
+        let name = match (&instruction.prefix, &instruction.name) {
+            (Prefix::Cargo, Some(name)) => name.as_str(),
+            _ => continue,
+        };
+        let value = instruction.value.to_string();
+
+        match name {
+            "rustc-link-lib" if links_libraries => args.push("-l", value),
+            "rustc-link-search" => args.push("-L", value),
+            "rustc-flags" => {
+                for flag in rustc_flags::parse(&value)? {
+                    match flag {
+                        Flag::LinkLib(..) if !links_libraries => {}
+                        Flag::LinkLib(..) => args.push("-l", lib_or_path(&flag)),
+                        Flag::LinkSearch(..) => args.push("-L", lib_or_path(&flag)),
+                    }
+                }
+            }
+            "rustc-cfg" => args.push("--cfg", value),
+            "rustc-check-cfg" => args.push("--check-cfg", value),
+            "rustc-env" => {
+                let (var, value) = value.split_once('=').unwrap_or((&value, ""));
+                args.env.insert(var.into(), value.into());
+            }
+            "rustc-link-arg" if !matches!(kind, TargetKind::Lib) => args.push_link_arg(&value),
+            "rustc-link-arg-bins" if matches!(kind, TargetKind::Bin(_)) => {
+                args.push_link_arg(&value)
+            }
+            "rustc-link-arg-bin" => {
+                if let (TargetKind::Bin(bin), Some((name, flag))) = (kind, value.split_once('=')) {
+                    if bin == name {
+                        args.push_link_arg(flag)
+                    }
+                }
+            }
+            "rustc-link-arg-tests" if matches!(kind, TargetKind::Test) => {
+                args.push_link_arg(&value)
+            }
+            "rustc-link-arg-benches" if matches!(kind, TargetKind::Bench) => {
+                args.push_link_arg(&value)
+            }
+            "rustc-link-arg-examples" if matches!(kind, TargetKind::Example) => {
+                args.push_link_arg(&value)
+            }
+            "rustc-cdylib-link-arg" | "rustc-link-arg-cdylib"
+                if matches!(kind, TargetKind::Cdylib) =>
+            {
+                args.push_link_arg(&value)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rustc_args, TargetKind};
+    use crate::rustc_flags;
+    use crate::{Instruction, Value};
+
+    fn instructions() -> Vec<Instruction> {
+        vec![
+            Instruction::new("rerun-if-changed", Value::Singular("build.rs".into())),
+            Instruction::new(
+                "rustc-link-lib",
+                Value::UnquotedOptionalKey(Some("static".into()), "foo".into()),
+            ),
+            Instruction::new(
+                "rustc-link-search",
+                Value::UnquotedOptionalKey(Some("native".into()), "/x".into()),
+            ),
+            Instruction::new(
+                "rustc-cfg",
+                Value::OptionalValue("k".into(), Some("v".into())),
+            ),
+            Instruction::new(
+                "rustc-env",
+                Value::UnquotedMapping("VERSION".into(), "1.0=final".into()),
+            ),
+            Instruction::new(
+                "rustc-cdylib-link-arg",
+                Value::Singular("-Wl,-soname,libfoo.so".into()),
+            ),
+            Instruction::new_mapping(Value::UnquotedMapping(
+                "include".into(),
+                "/x/include".into(),
+            )),
+            Instruction::new(
+                "rustc-link-arg-bin",
+                Value::UnquotedMapping("server".into(), "-Wl,--gc-sections".into()),
+            ),
+            Instruction::new("rustc-link-arg-tests", Value::Singular("-Wl,-z,now".into())),
+        ]
+    }
+
+    #[test]
+    fn test_lib() {
+        let args = rustc_args(&instructions(), &TargetKind::Lib, true).unwrap();
+        let expected = vec!["-l", "static=foo", "-L", "native=/x", "--cfg", "k=\"v\""];
+        assert_eq!(args.args, expected);
+        assert_eq!(args.env["VERSION"], "1.0=final")
+    }
+
+    #[test]
+    fn test_cdylib() {
+        let args = rustc_args(&instructions(), &TargetKind::Cdylib, true).unwrap();
+        assert_eq!(&args.args[6..], &["-C", "link-arg=-Wl,-soname,libfoo.so"])
+    }
+
+    #[test]
+    fn test_bin() {
+        let args = rustc_args(&instructions(), &TargetKind::Bin("server".into()), true).unwrap();
+        let expected = vec![
+            "-L",
+            "native=/x",
+            "--cfg",
+            "k=\"v\"",
+            "-C",
+            "link-arg=-Wl,--gc-sections",
+        ];
+        assert_eq!(args.args, expected);
+        let args = rustc_args(&instructions(), &TargetKind::Bin("client".into()), true).unwrap();
+        assert_eq!(args.args.len(), 4)
+    }
+
+    #[test]
+    fn test_without_library() {
+        let args = rustc_args(&instructions(), &TargetKind::Test, false).unwrap();
+        let expected = vec![
+            "-l",
+            "static=foo",
+            "-L",
+            "native=/x",
+            "--cfg",
+            "k=\"v\"",
+            "-C",
+            "link-arg=-Wl,-z,now",
+        ];
+        assert_eq!(args.args, expected)
+    }
+
+    #[test]
+    fn test_rustc_flags() {
+        let instructions = vec![Instruction::new(
+            "rustc-flags",
+            Value::Singular("-l ffi -L/opt/lib -lz".into()),
+        )];
+        let args = rustc_args(&instructions, &TargetKind::Lib, true).unwrap();
+        assert_eq!(args.args, vec!["-l", "ffi", "-L", "/opt/lib", "-l", "z"]);
+        let args = rustc_args(&instructions, &TargetKind::Example, true).unwrap();
+        assert_eq!(args.args, vec!["-L", "/opt/lib"]);
+
+        let instructions = vec![Instruction::new(
+            "rustc-flags",
+            Value::Singular("-l static:+whole-archive=z -L/opt/lib -g".into()),
+        )];
+        let error = rustc_args(&instructions, &TargetKind::Lib, true).unwrap_err();
+        assert_eq!(error, rustc_flags::Error::Unsupported("-g".into()));
+        let instructions = vec![Instruction::new(
+            "rustc-flags",
+            Value::Singular("-l static:+whole-archive=z".into()),
+        )];
+        let args = rustc_args(&instructions, &TargetKind::Lib, true).unwrap();
+        assert_eq!(args.args, vec!["-l", "static:+whole-archive=z"])
+    }
+}
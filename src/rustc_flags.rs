@@ -0,0 +1,258 @@
+//! Structured parsing for [`cargo_rustc_flags`](crate::BuildScript::cargo_rustc_flags).
+//! # Notes
+//! Cargo only allows `-l` and `-L` in `rustc-flags`, and fails the build on anything else.
+use crate::cargo_rustc_link_lib;
+use crate::cargo_rustc_link_search;
+use crate::{Instruction, Value};
+use std::fmt;
+use std::path::PathBuf;
+
+/// The modifiers `-l` accepts after its kind, without their `+` or `-`.
+const MODIFIERS: &[&str] = &["bundle", "verbatim", "whole-archive", "as-needed"];
+
+/// A flag allowed in `rustc-flags`.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Flag {
+    /// `-l [KIND[:MODIFIERS]=]NAME`, with the modifiers such as `+whole-archive,-bundle` kept as
+    /// written. Equivalent to `rustc-link-lib`.
+    LinkLib(Option<cargo_rustc_link_lib::Kind>, Option<String>, String),
+
+    /// `-L [KIND=]PATH`. Equivalent to `rustc-link-search`.
+    LinkSearch(Option<cargo_rustc_link_search::Kind>, PathBuf),
+}
+
+impl Flag {
+    /// Parse the value of an `-l` flag.
+    fn link_lib(value: &str) -> Result<Self, Error> {
+        let (kind, name) = match value.split_once('=') {
+            Some((kind, name)) => (kind, name),
+            None => return Ok(Self::LinkLib(None, None, value.into())),
+        };
+        let (kind, modifiers) = match kind.split_once(':') {
+            Some((kind, modifiers)) => {
+                for modifier in modifiers.split(',') {
+                    let known = modifier
+                        .strip_prefix(['+', '-'])
+                        .map(|name| MODIFIERS.contains(&name))
+                        .unwrap_or(false);
+
+                    if !known {
+                        return Err(Error::LinkLibModifier(modifier.into()));
+                    }
+                }
+
+                (kind, Some(modifiers.into()))
+            }
+            None => (kind, None),
+        };
+        let kind = kind.parse().map_err(Error::LinkLibKind)?;
+
+        Ok(Self::LinkLib(Some(kind), modifiers, name.into()))
+    }
+
+    /// Parse the value of an `-L` flag.
+    fn link_search(value: &str) -> Self {
+        // like rustc, only treat the prefix as a kind if it is a known one.
+        if let Some((kind, path)) = value.split_once('=') {
+            if let Ok(kind) = kind.parse() {
+                return Self::LinkSearch(Some(kind), path.into());
+            }
+        }
+
+        Self::LinkSearch(None, value.into())
+    }
+
+    /// Convert this into the equivalent `rustc-link-lib` or `rustc-link-search`
+    /// [`Instruction`](Instruction).
+    pub fn instruction(&self) -> Instruction {
+        match self {
+            Self::LinkLib(kind, modifiers, name) => {
+                let kind = kind.map(|kind| match modifiers {
+                    Some(modifiers) => format!("{}:{}", <&str>::from(kind), modifiers),
+                    None => kind.into(),
+                });
+
+                Instruction::new(
+                    "rustc-link-lib",
+                    Value::UnquotedOptionalKey(kind, name.clone()),
+                )
+            }
+            Self::LinkSearch(kind, path) => Instruction::new(
+                "rustc-link-search",
+                Value::UnquotedOptionalKey(kind.map(Into::into), path.display().to_string()),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LinkLib(Some(kind), Some(modifiers), name) => {
+                write!(f, "-l {}:{}={}", <&str>::from(*kind), modifiers, name)
+            }
+            Self::LinkLib(Some(kind), None, name) => {
+                write!(f, "-l {}={}", <&str>::from(*kind), name)
+            }
+            Self::LinkLib(None, _, name) => write!(f, "-l {}", name),
+            Self::LinkSearch(Some(kind), path) => {
+                write!(f, "-L {}={}", <&str>::from(*kind), path.display())
+            }
+            Self::LinkSearch(None, path) => write!(f, "-L {}", path.display()),
+        }
+    }
+}
+
+/// Parse the flags of `rustc-flags`. Both the joined (`-lfoo`) and separate (`-l foo`) forms are
+/// accepted.
+pub fn parse(flags: &str) -> Result<Vec<Flag>, Error> {
+    let mut parsed = Vec::new();
+    let mut flags = flags.split_whitespace();
+
+    while let Some(flag) = flags.next() {
+        let (name, value) = match (flag.get(..2), flag.get(2..)) {
+            (Some(name @ "-l"), Some(value)) | (Some(name @ "-L"), Some(value)) => {
+                let value = match value {
+                    "" => flags
+                        .next()
+                        .ok_or_else(|| Error::MissingValue(name.into()))?,
+                    value => value,
+                };
+
+                (name, value)
+            }
+            _ => return Err(Error::Unsupported(flag.into())),
+        };
+
+        parsed.push(match name {
+            "-l" => Flag::link_lib(value)?,
+            _ => Flag::link_search(value),
+        })
+    }
+
+    Ok(parsed)
+}
+
+/// An error which occurred while parsing `rustc-flags`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// The flag isn't allowed by cargo. Only `-l` and `-L` are.
+    Unsupported(String),
+
+    /// The flag is missing its value.
+    MissingValue(String),
+
+    /// An `-l` flag had an unknown kind.
+    LinkLibKind(cargo_rustc_link_lib::ParseKindError),
+
+    /// An `-l` flag had an unknown modifier, such as `+static`.
+    LinkLibModifier(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unsupported(flag) => write!(
+                f,
+                "unsupported flag `{}` in rustc-flags, only `-l` and `-L` are allowed",
+                flag
+            ),
+            Self::MissingValue(flag) => {
+                write!(f, "flag `{}` in rustc-flags is missing a value", flag)
+            }
+            Self::LinkLibKind(error) => write!(f, "invalid `-l` flag in rustc-flags: {}", error),
+            Self::LinkLibModifier(modifier) => write!(
+                f,
+                "invalid `-l` flag in rustc-flags: unknown modifier `{}`",
+                modifier
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::LinkLibKind(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Flag};
+    use crate::cargo_rustc_link_lib::Kind as LibKind;
+    use crate::cargo_rustc_link_search::Kind as SearchKind;
+
+    #[test]
+    fn test_parse() {
+        let flags = super::parse("-l ffi -lstatic=z -L native=/opt/lib -L/usr/lib").unwrap();
+        let expected = vec![
+            Flag::LinkLib(None, None, "ffi".into()),
+            Flag::LinkLib(Some(LibKind::Static), None, "z".into()),
+            Flag::LinkSearch(Some(SearchKind::Native), "/opt/lib".into()),
+            Flag::LinkSearch(None, "/usr/lib".into()),
+        ];
+        assert_eq!(flags, expected)
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        let error = super::parse("-l ffi -C opt-level=3").unwrap_err();
+        assert_eq!(error, Error::Unsupported("-C".into()))
+    }
+
+    #[test]
+    fn test_parse_missing_value() {
+        let error = super::parse("-l").unwrap_err();
+        assert_eq!(error, Error::MissingValue("-l".into()))
+    }
+
+    #[test]
+    fn test_parse_unknown_kind() {
+        assert!(matches!(
+            super::parse("-l native=foo"),
+            Err(Error::LinkLibKind(_))
+        ))
+    }
+
+    #[test]
+    fn test_parse_modifiers() {
+        let flags = super::parse("-l static:+whole-archive,-bundle=z").unwrap();
+        let expected = Flag::LinkLib(
+            Some(LibKind::Static),
+            Some("+whole-archive,-bundle".into()),
+            "z".into(),
+        );
+        assert_eq!(flags, vec![expected.clone()]);
+        assert_eq!(expected.to_string(), "-l static:+whole-archive,-bundle=z");
+        assert_eq!(
+            expected.instruction().to_string(),
+            "cargo:rustc-link-lib=static:+whole-archive,-bundle=z"
+        );
+        let error = super::parse("-l static:whole-archive=z").unwrap_err();
+        assert_eq!(error, Error::LinkLibModifier("whole-archive".into()));
+        assert!(super::parse("-l static:+static=z").is_err())
+    }
+
+    #[test]
+    fn test_instruction() {
+        let instructions: Vec<String> = super::parse("-l dylib=ffi -L crate=/opt")
+            .unwrap()
+            .iter()
+            .map(|flag| flag.instruction().to_string())
+            .collect();
+        let expected = vec![
+            "cargo:rustc-link-lib=dylib=ffi",
+            "cargo:rustc-link-search=crate=/opt",
+        ];
+        assert_eq!(instructions, expected)
+    }
+
+    #[test]
+    fn test_display() {
+        let flag = Flag::LinkLib(Some(LibKind::Framework), None, "Cocoa".into());
+        assert_eq!(flag.to_string(), "-l framework=Cocoa")
+    }
+}
@@ -0,0 +1,353 @@
+//! A [`BuildScript`](BuildScript) which can be shared between threads.
+//! # Notes
+//! Each logical task, such as compiling one native library, gets its own [`Task`](Task) which
+//! buffers its instructions. When a task is finished, its instructions are passed to the shared
+//! build script as a single block, after the blocks of every task created before it. The output
+//! therefore only depends on the order the tasks were created in, not on thread scheduling, as
+//! long as they are created before being sent to other threads.
+//! # Examples
+//! ```rust
+//! use build_script::shared::SharedBuildScript;
+//!
+//! let shared = SharedBuildScript::default();
+//! let tasks = vec![shared.task(), shared.task()];
+//!
+//! std::thread::scope(|scope| {
+//!     for (index, mut task) in tasks.into_iter().enumerate() {
+//!         scope.spawn(move || {
+//!             task.cargo_rustc_link_lib(None, &format!("native{}", index));
+//!         });
+//!     }
+//! });
+//!
+//! shared.build();
+//! ```
+use crate::core::Entry;
+use crate::intercept::Rejection;
+use crate::BuildScript;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// The state shared by every handle.
+struct Shared {
+    /// The build script the blocks are passed to.
+    build_script: BuildScript<'static>,
+
+    /// The blocks of every task, in the order the tasks were created, with the instructions the
+    /// interceptors of the task rejected. A block is [`None`](None) until its task is finished.
+    blocks: Vec<Option<(Vec<Entry>, Vec<Rejection>)>>,
+
+    /// The number of blocks already passed to the build script.
+    flushed: usize,
+}
+
+impl Shared {
+    /// Pass every finished block which isn't waiting on an earlier one to the build script, through
+    /// its interceptors.
+    fn flush(&mut self) {
+        while let Some(Some((block, rejections))) =
+            self.blocks.get_mut(self.flushed).map(Option::take)
+        {
+            self.build_script.push_rejections(rejections);
+
+            for entry in block {
+                self.build_script.push_entry(entry)
+            }
+
+            self.flushed += 1
+        }
+    }
+}
+
+/// A cloneable handle to a [`BuildScript`](BuildScript) which can be shared between threads.
+#[derive(Clone)]
+pub struct SharedBuildScript {
+    /// The shared state.
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Default for SharedBuildScript {
+    /// Share the default build script. See [`BuildScript::default()`](BuildScript::default).
+    fn default() -> Self {
+        Self::new(BuildScript::default())
+    }
+}
+
+impl SharedBuildScript {
+    /// Share a build script. The blocks of finished tasks are passed to it, so it may either
+    /// write them immediately (`now` mode) or queue them until [`build()`](Self::build).
+    pub fn new(build_script: BuildScript<'static>) -> Self {
+        let shared = Shared {
+            build_script,
+            blocks: Vec::new(),
+            flushed: 0,
+        };
+
+        Self {
+            shared: Arc::new(Mutex::new(shared)),
+        }
+    }
+
+    /// Lock the shared state. A task panicking while holding the lock doesn't leave the state
+    /// inconsistent, so a poisoned mutex is recovered from, like [`Task`](Task) does when dropped.
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Create a new task. Its block is passed after the blocks of every task created before it.
+    pub fn task(&self) -> Task {
+        let mut shared = self.lock();
+        let index = shared.blocks.len();
+        shared.blocks.push(None);
+
+        Task {
+            shared: self.clone(),
+            index,
+            build_script: BuildScript::buffer(),
+        }
+    }
+
+    /// Access the shared build script directly, such as to configure it or pass instructions
+    /// which don't belong to any task.
+    pub fn with<R>(&self, func: impl FnOnce(&mut BuildScript<'static>) -> R) -> R {
+        func(&mut self.lock().build_script)
+    }
+
+    /// Build the shared build script. See [`BuildScript::build()`](BuildScript::build).
+    /// # Notes
+    /// Blocks waiting on a task which isn't finished yet aren't written.
+    pub fn build(&self) {
+        self.lock().build_script.build()
+    }
+}
+
+/// A logical task of a [`SharedBuildScript`](SharedBuildScript). This dereferences to a
+/// [`BuildScript`](BuildScript) which buffers the instructions of the task.
+/// # Notes
+/// The task is finished when it is dropped. Its instructions then pass through the interceptors of
+/// the shared build script, and those its own interceptors rejected fail the shared build script.
+/// If it is dropped while panicking, its instructions are discarded.
+///
+/// The buffering build script writes to a sink, so it must neither be put in `now` mode nor be
+/// built with [`build()`](BuildScript::build) or [`try_build()`](BuildScript::try_build), or its
+/// instructions will be silently lost. Use [`SharedBuildScript::build()`](SharedBuildScript::build)
+/// instead.
+pub struct Task {
+    /// The shared build script.
+    shared: SharedBuildScript,
+
+    /// The index of the block of this task.
+    index: usize,
+
+    /// The build script buffering the instructions of this task.
+    build_script: BuildScript<'static>,
+}
+
+impl Task {
+    /// Finish the task, passing its instructions to the shared build script. This is the same as
+    /// dropping it.
+    pub fn finish(self) {}
+}
+
+impl Deref for Task {
+    type Target = BuildScript<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.build_script
+    }
+}
+
+impl DerefMut for Task {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.build_script
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        let block = if thread::panicking() {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                self.build_script.take_entries(),
+                self.build_script.take_rejections(),
+            )
+        };
+        // don't panic while dropping, and always fill the block so later ones are flushed.
+        let mut shared = self.shared.lock();
+
+        shared.blocks[self.index] = Some(block);
+        shared.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedBuildScript;
+    use crate::BuildScript;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Writer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Writer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Writer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap())
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    fn shared(now: bool) -> (SharedBuildScript, Writer) {
+        let writer = Writer::default();
+        let mut build_script = BuildScript::new(Box::leak(Box::new(writer.clone())));
+
+        if now {
+            build_script.now();
+        }
+
+        (SharedBuildScript::new(build_script), writer)
+    }
+
+    #[test]
+    fn test_blocks_in_creation_order() {
+        let (shared, writer) = shared(true);
+        let mut first = shared.task();
+        let mut second = shared.task();
+        second.cargo_rustc_link_lib(None, "second");
+        second.finish();
+        assert!(writer.lines().is_empty());
+        first.cargo_rustc_link_lib(None, "first");
+        first.cargo_rustc_link_search(None, "first".into());
+        first.finish();
+        let expected = vec![
+            "cargo:rustc-link-lib=first",
+            "cargo:rustc-link-search=first",
+            "cargo:rustc-link-lib=second",
+        ];
+        assert_eq!(writer.lines(), expected)
+    }
+
+    #[test]
+    fn test_threads() {
+        let (shared, writer) = shared(false);
+        let tasks: Vec<_> = (0..8).map(|_| shared.task()).collect();
+
+        std::thread::scope(|scope| {
+            for (index, mut task) in tasks.into_iter().enumerate() {
+                scope.spawn(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(8 - index as u64));
+                    task.cargo_rustc_link_lib(None, &format!("lib{}", index));
+                    task.cargo_rustc_cfg(&format!("cfg{}", index), None);
+                });
+            }
+        });
+
+        shared.with(|build_script| {
+            build_script.cargo_warning("done");
+        });
+        shared.build();
+        let mut expected: Vec<String> = (0..8)
+            .flat_map(|index| {
+                vec![
+                    format!("cargo:rustc-link-lib=lib{}", index),
+                    format!("cargo:rustc-cfg=cfg{}", index),
+                ]
+            })
+            .collect();
+        expected.push("cargo:warning=done".into());
+        assert_eq!(writer.lines(), expected)
+    }
+
+    #[test]
+    fn test_panicking_task_is_discarded() {
+        let (shared, writer) = shared(true);
+        let mut task = shared.task();
+        let result = std::thread::spawn(move || {
+            task.cargo_rustc_link_lib(None, "half");
+            panic!("failed");
+        })
+        .join();
+        assert!(result.is_err());
+        shared.task().cargo_rustc_link_lib(None, "next");
+        assert_eq!(writer.lines(), vec!["cargo:rustc-link-lib=next"])
+    }
+
+    #[test]
+    fn test_intercepted() {
+        use crate::intercept::Action;
+        use crate::Instruction;
+
+        let (shared, writer) = shared(false);
+        shared.with(|build_script| {
+            build_script.intercept(
+                |instruction: Instruction| match instruction.name.as_deref() {
+                    Some("rustc-flags") => Action::Reject("rustc-flags is forbidden".into()),
+                    _ => Action::Pass(instruction),
+                },
+            );
+        });
+        let mut task = shared.task();
+        task.cargo_rustc_flags("-l ssl");
+        task.cargo_rustc_link_lib(None, "ssl");
+        task.finish();
+        let mut task = shared.task();
+        task.intercept(|_| Action::Reject("nothing is allowed".into()));
+        task.cargo_rustc_link_lib(None, "z");
+        task.finish();
+        let rejections = shared.with(|build_script| {
+            assert!(build_script.try_build().is_err());
+            build_script.take_rejections()
+        });
+        let reasons: Vec<_> = rejections
+            .iter()
+            .map(|rejection| &rejection.reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            vec!["rustc-flags is forbidden", "nothing is allowed"]
+        );
+        shared.build();
+        assert_eq!(writer.lines(), vec!["cargo:rustc-link-lib=ssl"])
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let (shared, writer) = shared(true);
+        let mut first = shared.task();
+        let mut second = shared.task();
+        second.cargo_rustc_link_lib(None, "second");
+        let poisoner = shared.clone();
+        let result = std::thread::spawn(move || {
+            poisoner.with(|_| panic!("failed"));
+        })
+        .join();
+        assert!(result.is_err());
+        first.cargo_rustc_link_lib(None, "first");
+        // the first block is filled even though the lock is poisoned, so the second one follows.
+        drop(first);
+        drop(second);
+        // later tasks and builds still work.
+        shared.task().cargo_rustc_link_lib(None, "third");
+        shared.build();
+        let expected = vec![
+            "cargo:rustc-link-lib=first",
+            "cargo:rustc-link-lib=second",
+            "cargo:rustc-link-lib=third",
+        ];
+        assert_eq!(writer.lines(), expected)
+    }
+}
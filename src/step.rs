@@ -0,0 +1,107 @@
+//! Reusable units of build script logic, such as "locate and link OpenSSL". See
+//! [`BuildScript::run_steps()`](crate::BuildScript::run_steps).
+//! # Examples
+//! ```rust
+//! use build_script::step::{BuildStep, Input};
+//! use build_script::BuildScript;
+//! use std::error::Error;
+//!
+//! struct LinkZlib;
+//!
+//! impl BuildStep for LinkZlib {
+//!     fn name(&self) -> &str {
+//!         "link zlib"
+//!     }
+//!
+//!     fn inputs(&self) -> Vec<Input> {
+//!         vec![Input::Env("ZLIB_DIR".into())]
+//!     }
+//!
+//!     fn run(&self, build_script: &mut BuildScript) -> Result<(), Box<dyn Error + Send + Sync>> {
+//!         let dir = std::env::var("ZLIB_DIR").unwrap_or_else(|_| "/usr/lib".into());
+//!         build_script.cargo_rustc_link_search(None, dir.into());
+//!         build_script.cargo_rustc_link_lib(None, "z");
+//!
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut build_script = BuildScript::default();
+//! build_script.run_steps(&[&LinkZlib]).unwrap();
+//! build_script.build();
+//! ```
+use crate::BuildScript;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Something a [`BuildStep`](BuildStep) depends on. Cargo reruns the build script when it
+/// changes.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Input {
+    /// A file or directory. Emitted as `rerun-if-changed`.
+    File(PathBuf),
+
+    /// An environment variable. Emitted as `rerun-if-env-changed`.
+    Env(String),
+}
+
+/// A reusable unit of build script logic.
+pub trait BuildStep {
+    /// The name of the step, which is attached to its instructions, warnings and failures.
+    fn name(&self) -> &str;
+
+    /// What the step depends on. This is empty by default.
+    fn inputs(&self) -> Vec<Input> {
+        Vec::new()
+    }
+
+    /// Run the step, passing its instructions to `build_script`. If this fails, the instructions
+    /// it passed are discarded.
+    fn run(
+        &self,
+        build_script: &mut BuildScript,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// A step which failed.
+#[derive(Debug)]
+pub struct Failure {
+    /// The name of the step.
+    pub step: String,
+
+    /// Why it failed.
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step `{}` failed: {}", self.step, self.error)
+    }
+}
+
+impl std::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+/// Every step which failed in [`BuildScript::run_steps()`](crate::BuildScript::run_steps).
+#[derive(Debug)]
+pub struct Error {
+    /// The failed steps, in the order they were run.
+    pub failures: Vec<Failure>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} build step(s) failed", self.failures.len())?;
+
+        for failure in &self.failures {
+            write!(f, "\n{}", failure)?
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
@@ -25,9 +25,47 @@ impl<T> VecExt<T> for Vec<T> {
     }
 }
 
+/// A directory for a test, unique to the process and the call, which is removed when dropped.
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Create a new directory, named after `name`.
+    pub(crate) fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Makes the directories of the same test run twice unique.
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "build_script_test_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).expect("failed to create the test directory");
+
+        Self(path)
+    }
+
+    /// The path of the directory.
+    pub(crate) fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // a failed cleanup shouldn't hide why the test failed.
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::VecExt;
+    use super::{TempDir, VecExt};
 
     #[test]
     fn test_take() {
@@ -47,4 +85,15 @@ mod tests {
         let none = vec.take_first();
         assert!(none.is_none())
     }
+
+    #[test]
+    fn test_temp_dir() {
+        let first = TempDir::new("temp_dir");
+        let second = TempDir::new("temp_dir");
+        assert_ne!(first.path(), second.path());
+        let path = first.path().to_path_buf();
+        std::fs::write(path.join("file"), "").unwrap();
+        drop(first);
+        assert!(!path.exists())
+    }
 }
@@ -2,7 +2,15 @@
 use std::fmt;
 
 /// The value of an [`Instruction`](crate::Instruction).
+/// # Notes
+/// With the `serde` feature, this is serialized as an object with the `snake_case` name of the
+/// variant as its only key, such as `{"optional_value": ["key", null]}`.
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Value {
     /// A singular value.
     Singular(String),
@@ -215,4 +223,14 @@ mod tests {
         Value::UnquotedMapping("key".into(), "value".into()),
         "key=value"
     );
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let value = Value::OptionalValue("key".into(), None);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"optional_value":["key",null]}"#);
+        let value: Value = serde_json::from_str(r#"{"singular":"singular"}"#).unwrap();
+        assert_eq!(value, Value::Singular("singular".into()))
+    }
 }